@@ -22,3 +22,145 @@ pull-model = Pull model
 remove-model = Remove model
 user-avatar = Set avatar
 keep-alive = How long to keep model in memory (string)
+export = Export
+export-all = Export all
+export-format = Export format
+format-markdown = Markdown
+format-html = HTML
+format-json = JSON (Ollama chat)
+format-plain-text = Plain text
+embed-images = Embed images in Markdown
+export-conversation = Export conversation
+export-all-conversations = Export all saved conversations
+export-user = User
+export-assistant = Assistant
+export-image = [image]
+exported = Exported to { $path }
+exported-all = Exported { $count } conversations to { $path }
+export-failed = Export failed: { $reason }
+import = Import
+cancel = Cancel
+import-conversations = Import from Open WebUI, ChatGPT or Ollama JSON
+import-summary = { $source }: { $conversations } conversations with { $messages } messages and { $images } images will be imported, { $duplicates } duplicates skipped
+imported = Imported { $count } conversations
+imported-partially = Imported { $count } conversations; these could not be written: { $failed }
+import-failed = Import failed: { $reason }
+editing-message = Editing an earlier message, everything after it will be regenerated
+fork-title = Fork of { $origin }
+regenerate = Regenerate
+edit-message = Edit
+fork-from-here = Fork into a new chat from here
+fork-failed = Could not keep the original conversation, so it was not forked: { $reason }
+context-strategy = When the chat outgrows the model's context
+context-window = Turns kept by the sliding window
+strategy-truncate = Drop oldest messages
+strategy-summarize = Summarize oldest messages
+strategy-sliding-window = Sliding window
+context-usage = Context: ~{ $used } / { $length } tokens
+summarizing = Summarizing earlier messages...
+summary-of-earlier-messages = Summary of earlier messages
+summary-failed = Could not summarize earlier messages: { $reason }
+stats-show = Show statistics
+stats-tokens-per-second = { $rate } tokens/s
+stats-first-token = first token after { $duration } s
+stats-load = model load { $duration } s
+stats-prompt-tokens = { $count } prompt tokens
+stats-response-tokens = { $count } response tokens
+stats-total = { $duration } s total
+stats-conversation-total = Total: { $prompt } prompt + { $response } response tokens in { $duration } s
+usage = Usage
+usage-statistics = Usage statistics
+usage-per-model = Per model
+usage-per-day = Tokens per day
+usage-model-summary = { $requests } requests, { $rate } tokens/s, { $cold } cold loads
+usage-tokens = { $tokens } tokens
+usage-empty = No requests recorded yet
+servers = Servers
+server-name = Name
+server-name-default = Server { $number }
+add-server = Add
+remove-server = Remove
+settings-unreadable = The settings could not be read and were moved aside; defaults are used: { $reason }
+server-removed = Server removed
+ca-certificate = CA certificate
+none = None
+bearer-token = Bearer token
+header-name = Header
+header-value = Value
+add-header = Add header
+save-credentials = Save credentials
+credentials-saved = Credentials saved to the keyring
+credentials-failed = Keyring error: { $reason }
+backend = Protocol
+backend-ollama = Ollama
+backend-openai = OpenAI-compatible
+status-connecting = Connecting…
+status-connected = Connected
+status-connected-version = Connected · version { $version }
+status-disconnected = Disconnected
+test-connection = Test connection
+testing-connection = Testing connection…
+connection-ok = Connected in { $latency } ms (version { $version })
+connection-failed = Connection failed: { $reason }
+choose-model = Choose a model
+search-models = Search models
+favorites = Favorites
+favorite = Favorite
+family-other = Other
+no-models-found = No models found
+capability-vision = Vision
+capability-tools = Tools
+capability-thinking = Thinking
+capability-embedding = Embedding
+model-preset = Model preset
+capabilities = Capabilities
+system-prompt = System prompt
+temperature = Temperature
+top-p = Top P
+top-k = Top K
+context-size = Context size
+seed = Seed
+think = Thinking
+think-default = Model default
+think-on = On
+think-off = Off
+back = Back
+reset = Reset
+compare = Compare
+comparing = Comparing { $models }
+compare-pick-more = Pick another model to compare with
+compare-pick = Continue with this reply
+request-failed = Request failed: { $reason }
+pulling = Pulling…
+removing = Removing…
+reply-stopped = Stopped
+continue-reply = Continue
+queued-prompts = Queued prompts
+send-now = Send now
+move-up = Move up
+prompt-templates = Prompt templates
+prompt-templates-count = { $count } templates, used by typing /name in the prompt
+templates-failed = Cannot read the prompt templates: { $reason }
+open-folder = Open folder
+send = Send
+paste-image = Paste image
+ask-about-clipboard = Ask about the clipboard
+clipboard-no-image = There is no image on the clipboard.
+clipboard-no-text = There is no text on the clipboard.
+capture-screen = Capture screen or region
+screenshot-failed = Screenshot failed: { $reason }
+selection-empty = Nothing is selected.
+keyboard-shortcuts = Keyboard shortcuts
+key-stop = Stop the reply or close
+key-new-chat = New chat
+key-clear-chat = Clear chat
+key-save-conversation = Save conversation
+key-settings = Settings
+key-edit-last = Edit the last message
+key-copy-last-reply = Copy the last reply
+key-binding-invalid = "{ $binding }" is not a key binding; use e.g. Ctrl+Shift+C, or a named key such as Escape
+key-binding-taken = { $binding } is already used for: { $action }
+pasted-text = Pasted text · { $lines } lines
+pasted-text-expand = Move into the prompt
+prompt-counter = { $characters } characters · ~{ $tokens } tokens
+load-failed = Could not load the conversation: { $reason }
@@ -1,35 +1,251 @@
-use futures::Stream;
-use reqwest::{Client, StatusCode};
+use futures::{
+    future::BoxFuture,
+    stream::{BoxStream, Stream, StreamExt},
+    FutureExt,
+};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION},
+    Certificate, Client, StatusCode,
+};
 use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    time::{Duration, Instant},
+};
+
+use crate::{fl, openai::OpenAi, secrets, ServerProfile};
+
+/// The protocol a server profile speaks.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Backend {
+    #[default]
+    Ollama,
+    /// `/v1/chat/completions`, as served by llama.cpp, vLLM or LM Studio.
+    OpenAi,
+}
 
-use crate::Settings;
+impl Backend {
+    pub const ALL: [Backend; 2] = [Backend::Ollama, Backend::OpenAi];
 
-#[derive(Serialize)]
-struct GenerateNonContext {
-    model: String,
-    prompt: String,
-    images: Vec<String>,
-    stream: bool,
-    keep_alive: String,
+    pub fn name(&self) -> String {
+        match self {
+            Backend::Ollama => fl!("backend-ollama"),
+            Backend::OpenAi => fl!("backend-openai"),
+        }
+    }
+
+    /// Whether models can be pulled and removed through the server.
+    pub fn manages_models(&self) -> bool {
+        *self == Backend::Ollama
+    }
+
+    /// Whether the server honours `keep_alive`.
+    pub fn keeps_alive(&self) -> bool {
+        *self == Backend::Ollama
+    }
+}
+
+/// Replies in Ollama's shape, whatever the backend.
+pub type Responses = BoxStream<'static, anyhow::Result<BotResponse>>;
+
+/// A chat server. Futures are boxed so that the provider can be chosen per
+/// server profile at runtime.
+pub trait Provider: Send + Sync {
+    fn chat(
+        &self,
+        model: String,
+        messages: Vec<ChatMessage>,
+        parameters: Parameters,
+    ) -> BoxFuture<'static, anyhow::Result<Responses>>;
+
+    fn complete(
+        &self,
+        model: String,
+        messages: Vec<ChatMessage>,
+        parameters: Parameters,
+    ) -> BoxFuture<'static, anyhow::Result<BotResponse>>;
+
+    /// Context length of the model, when the server tells.
+    fn context_length(&self, model: String) -> BoxFuture<'static, anyhow::Result<Option<u64>>>;
+
+    /// Models in Ollama's `/api/tags` shape; other servers fill in what
+    /// they know.
+    fn list_models(&self) -> BoxFuture<'static, anyhow::Result<Tags>>;
+
+    /// Fails when the server cannot be reached; the version is only known
+    /// to some servers.
+    fn version(&self) -> BoxFuture<'static, anyhow::Result<Option<String>>>;
+}
+
+/// Model details asked for at once when listing models.
+const SHOW_REQUESTS: usize = 4;
+
+/// The provider speaking the connection's backend.
+pub fn provider(connection: Connection) -> Box<dyn Provider> {
+    match connection.backend {
+        Backend::Ollama => Box::new(Ollama { connection }),
+        Backend::OpenAi => Box::new(OpenAi { connection }),
+    }
+}
+
+/// How to reach a server profile: its base URL, and a client carrying the
+/// profile's headers and extra certificate. Clones share the client and so
+/// its pooled connections.
+#[derive(Clone)]
+pub struct Connection {
+    pub backend: Backend,
+    pub base_url: String,
+    client: Client,
+}
+
+impl std::fmt::Debug for Connection {
+    // Headers carry credentials.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Connection")
+            .field("backend", &self.backend)
+            .field("base_url", &self.base_url)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Connection {
+    pub fn new(server: &ServerProfile) -> anyhow::Result<Self> {
+        let credentials = secrets::get(&server.id);
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in &credentials.headers {
+            headers.insert(
+                HeaderName::from_bytes(name.trim().as_bytes())?,
+                HeaderValue::from_str(value.trim())?,
+            );
+        }
+        if !credentials.token.is_empty() {
+            let mut value = HeaderValue::from_str(&format!("Bearer {}", credentials.token))?;
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+
+        let certificate = if server.ca_certificate.as_os_str().is_empty() {
+            None
+        } else {
+            let data = fs::read(&server.ca_certificate)?;
+            Some(Certificate::from_pem(&data).or_else(|_| Certificate::from_der(&data))?)
+        };
+
+        let mut builder = Client::builder().default_headers(headers);
+        if let Some(certificate) = certificate {
+            builder = builder.add_root_certificate(certificate);
+        }
+
+        Ok(Self {
+            backend: server.backend,
+            base_url: base_url(&server.address),
+            client: builder.build()?,
+        })
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+}
+
+/// Older settings only hold `host:port`, which is reached over plain HTTP.
+fn base_url(address: &str) -> String {
+    let address = address.trim().trim_end_matches('/');
+
+    if address.starts_with("http://") || address.starts_with("https://") {
+        address.to_string()
+    } else {
+        format!("http://{}", address)
+    }
+}
+
+/// Splits a streamed body into lines, however the chunks happen to be cut.
+pub fn lines(
+    body: impl Stream<Item = reqwest::Result<bytes::Bytes>> + Send + 'static,
+) -> impl Stream<Item = anyhow::Result<String>> + Send + 'static {
+    async_stream::try_stream! {
+        let mut body = Box::pin(body);
+        let mut buffer: Vec<u8> = Vec::new();
+
+        while let Some(chunk) = body.next().await {
+            buffer.extend_from_slice(&chunk?);
+
+            while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                yield String::from_utf8_lossy(&line).trim_end().to_string();
+            }
+        }
+
+        if !buffer.is_empty() {
+            yield String::from_utf8_lossy(&buffer).trim_end().to_string();
+        }
+    }
+}
+
+/// Sampling options; unset ones are left to the server.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Options {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+}
+
+/// Everything about a chat request besides the model and the messages.
+#[derive(Debug, Clone, Default)]
+pub struct Parameters {
+    pub keep_alive: String,
+    pub options: Options,
+    /// Asks thinking models to think, or not; unset leaves it to the model.
+    pub think: Option<bool>,
 }
 
 #[derive(Serialize)]
-struct GenerateWithContext {
+struct ChatQuery {
     model: String,
-    prompt: String,
+    messages: Vec<ChatMessage>,
     stream: bool,
-    images: Vec<String>,
-    context: Vec<u64>,
     keep_alive: String,
+    options: Options,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    think: Option<bool>,
+}
+
+impl ChatQuery {
+    fn new(
+        model: String,
+        messages: Vec<ChatMessage>,
+        parameters: Parameters,
+        stream: bool,
+    ) -> Self {
+        Self {
+            model,
+            messages,
+            stream,
+            keep_alive: parameters.keep_alive,
+            options: parameters.options,
+            think: parameters.think,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct BotResponse {
     pub model: String,
     pub created_at: String,
-    pub response: String,
+    pub message: Option<ChatMessage>,
     pub done: bool,
-    pub context: Option<Vec<u64>>,
     pub total_duration: Option<u64>,
     pub load_duration: Option<u64>,
     pub prompt_eval_count: Option<u64>,
@@ -38,59 +254,270 @@ pub struct BotResponse {
     pub eval_duration: Option<u64>,
 }
 
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<String>,
+}
+
+/// Ollama's native API, streaming NDJSON.
+struct Ollama {
+    connection: Connection,
+}
+
+impl Ollama {
+    async fn show(connection: &Connection, model: String) -> anyhow::Result<ShowModelResponse> {
+        Ok(connection
+            .client()
+            .post(connection.url("/api/show"))
+            .json::<ShowModelQuery>(&ShowModelQuery { model })
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<ShowModelResponse>()
+            .await?)
+    }
+}
+
+impl Provider for Ollama {
+    fn chat(
+        &self,
+        model: String,
+        messages: Vec<ChatMessage>,
+        parameters: Parameters,
+    ) -> BoxFuture<'static, anyhow::Result<Responses>> {
+        let connection = self.connection.clone();
+
+        async move {
+            let query = ChatQuery::new(model, messages, parameters, true);
+
+            let body = connection
+                .client()
+                .post(connection.url("/api/chat"))
+                .json::<ChatQuery>(&query)
+                .send()
+                .await?
+                .error_for_status()?
+                .bytes_stream();
+
+            let responses = lines(body).filter_map(|line| async move {
+                match line {
+                    Ok(line) if line.trim().is_empty() => None,
+                    Ok(line) => {
+                        Some(serde_json::from_str::<BotResponse>(&line).map_err(Into::into))
+                    }
+                    Err(why) => Some(Err(why)),
+                }
+            });
+
+            Ok(responses.boxed())
+        }
+        .boxed()
+    }
+
+    fn complete(
+        &self,
+        model: String,
+        messages: Vec<ChatMessage>,
+        parameters: Parameters,
+    ) -> BoxFuture<'static, anyhow::Result<BotResponse>> {
+        let connection = self.connection.clone();
+
+        async move {
+            let query = ChatQuery::new(model, messages, parameters, false);
+
+            Ok(connection
+                .client()
+                .post(connection.url("/api/chat"))
+                .json::<ChatQuery>(&query)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<BotResponse>()
+                .await?)
+        }
+        .boxed()
+    }
+
+    fn context_length(&self, model: String) -> BoxFuture<'static, anyhow::Result<Option<u64>>> {
+        let connection = self.connection.clone();
+
+        async move {
+            let response = Ollama::show(&connection, model).await?;
+
+            // A `num_ctx` set in the Modelfile is what the server actually uses;
+            // the architecture maximum is only a fallback.
+            let num_ctx = response.parameters.lines().find_map(|line| {
+                let mut parts = line.split_whitespace();
+                (parts.next() == Some("num_ctx"))
+                    .then(|| parts.next()?.parse::<u64>().ok())
+                    .flatten()
+            });
+
+            Ok(num_ctx.or_else(|| {
+                response
+                    .model_info
+                    .iter()
+                    .find(|(key, _)| key.ends_with(".context_length"))
+                    .and_then(|(_, value)| value.as_u64())
+            }))
+        }
+        .boxed()
+    }
+
+    /// `/api/tags` lacks capabilities, so every model is also looked up.
+    fn list_models(&self) -> BoxFuture<'static, anyhow::Result<Tags>> {
+        let connection = self.connection.clone();
+
+        async move {
+            let mut tags = connection
+                .client()
+                .get(connection.url("/api/tags"))
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<Tags>()
+                .await?;
+
+            // A few at a time, over the client's shared connections.
+            let details: Vec<_> = futures::stream::iter(&tags.models)
+                .map(|model| Ollama::show(&connection, model.model.clone()))
+                .buffered(SHOW_REQUESTS)
+                .collect()
+                .await;
+
+            for (model, details) in tags.models.iter_mut().zip(details) {
+                if let Ok(details) = details {
+                    model.capabilities = details.capabilities;
+                }
+            }
+
+            Ok(tags)
+        }
+        .boxed()
+    }
+
+    fn version(&self) -> BoxFuture<'static, anyhow::Result<Option<String>>> {
+        let connection = self.connection.clone();
+
+        async move {
+            let response = connection
+                .client()
+                .get(connection.url("/api/version"))
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<VersionResponse>()
+                .await?;
+
+            Ok(Some(response.version))
+        }
+        .boxed()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct VersionResponse {
+    version: String,
+}
+
+/// Result of a health check.
+#[derive(Debug, Clone)]
+pub struct Version {
+    pub version: Option<String>,
+    pub latency: Duration,
+}
+
+impl Version {
+    pub async fn new(connection: Connection) -> anyhow::Result<Self> {
+        let provider = provider(connection);
+        let started = Instant::now();
+        let version = provider.version().await?;
+
+        Ok(Self {
+            version,
+            latency: started.elapsed(),
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct Bot {}
 
 impl Bot {
     pub async fn new(
+        connection: Connection,
         model: String,
-        prompt: String,
-        images: Vec<String>,
-        context: Option<Vec<u64>>,
-        keep_alive: String,
-    ) -> anyhow::Result<(
-        Self,
-        impl Stream<Item = anyhow::Result<bytes::Bytes, reqwest::Error>>,
-    )> {
-        let settings = Settings::load();
-        let client = Client::new().post(format!("http://{}/api/generate", settings.ollama_address));
-
-        let stream = if context.is_none() {
-            let no_context_query = GenerateNonContext {
-                model,
-                prompt,
-                images,
-                keep_alive,
-                stream: true,
-            };
-
-            client
-                .json::<GenerateNonContext>(&no_context_query)
-                .send()
-                .await
-                .unwrap()
-                .bytes_stream()
-        } else {
-            let context_query = GenerateWithContext {
-                model,
-                prompt,
-                images,
-                stream: true,
-                keep_alive,
-                context: context.unwrap(),
-            };
-
-            client
-                .json::<GenerateWithContext>(&context_query)
-                .send()
-                .await
-                .unwrap()
-                .bytes_stream()
-        };
+        messages: Vec<ChatMessage>,
+        parameters: Parameters,
+    ) -> anyhow::Result<(Self, Responses)> {
+        let responses = provider(connection)
+            .chat(model, messages, parameters)
+            .await?;
 
         let bot = Self {};
 
-        Ok((bot, stream))
+        Ok((bot, responses))
+    }
+}
+
+#[derive(Debug)]
+pub struct Completion {
+    pub response: BotResponse,
+}
+
+impl Completion {
+    /// Single, non-streamed chat request.
+    pub async fn new(
+        connection: Connection,
+        model: String,
+        messages: Vec<ChatMessage>,
+        parameters: Parameters,
+    ) -> anyhow::Result<Self> {
+        let response = provider(connection)
+            .complete(model, messages, parameters)
+            .await?;
+
+        Ok(Self { response })
+    }
+}
+
+#[derive(Serialize)]
+struct ShowModelQuery {
+    model: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShowModelResponse {
+    #[serde(default)]
+    parameters: String,
+    #[serde(default)]
+    model_info: serde_json::Map<String, serde_json::Value>,
+    /// Missing before Ollama 0.6.
+    #[serde(default)]
+    capabilities: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ShowModel {
+    pub context_length: Option<u64>,
+}
+
+impl ShowModel {
+    pub async fn new(connection: Connection, model: String) -> anyhow::Result<Self> {
+        let context_length = provider(connection).context_length(model).await?;
+
+        Ok(Self { context_length })
     }
 }
 
@@ -109,22 +536,22 @@ pub struct PullModel {}
 
 impl PullModel {
     pub async fn new(
+        connection: Connection,
         model: String,
-    ) -> anyhow::Result<(
-        Self,
-        impl Stream<Item = anyhow::Result<bytes::Bytes, reqwest::Error>>,
-    )> {
-        let settings = Settings::load();
-        let client = Client::new().post(format!("http://{}/api/pull", settings.ollama_address));
+    ) -> anyhow::Result<(Self, impl Stream<Item = anyhow::Result<String>>)> {
+        if !connection.backend.manages_models() {
+            anyhow::bail!("this server cannot pull models");
+        }
+        let client = connection.client().post(connection.url("/api/pull"));
 
         let pull_query = PullModelQuery { name: model };
 
-        let stream = client
+        let response = client
             .json::<PullModelQuery>(&pull_query)
             .send()
-            .await
-            .unwrap()
-            .bytes_stream();
+            .await?
+            .error_for_status()?;
+        let stream = lines(response.bytes_stream());
 
         let pull = Self {};
 
@@ -141,9 +568,11 @@ pub struct RemoveModelQuery {
 pub struct RemoveModel {}
 
 impl RemoveModel {
-    pub async fn new(model: String) -> anyhow::Result<(Self, StatusCode)> {
-        let settings = Settings::load();
-        let client = Client::new().delete(format!("http://{}/api/delete", settings.ollama_address));
+    pub async fn new(connection: Connection, model: String) -> anyhow::Result<(Self, StatusCode)> {
+        if !connection.backend.manages_models() {
+            anyhow::bail!("this server cannot remove models");
+        }
+        let client = connection.client().delete(connection.url("/api/delete"));
 
         let remove_query = RemoveModelQuery { name: model };
 
@@ -159,12 +588,12 @@ impl RemoveModel {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Tags {
     pub models: Vec<Model>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Model {
     pub name: String,
     pub model: String,
@@ -172,9 +601,12 @@ pub struct Model {
     pub size: Option<u64>,
     pub digest: Option<String>,
     pub details: Option<ModelDetails>,
+    /// Filled in from `/api/show`, e.g. `vision` or `tools`.
+    #[serde(default)]
+    pub capabilities: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelDetails {
     pub format: Option<String>,
     pub family: Option<String>,
@@ -185,25 +617,13 @@ pub struct ModelDetails {
 
 #[derive(Debug)]
 pub struct ListModels {
-    pub result: anyhow::Result<Tags, reqwest::Error>,
+    pub tags: Tags,
 }
 
 impl ListModels {
-    pub fn new() -> Self {
-        let settings = Settings::load();
-        let client = reqwest::blocking::Client::new()
-            .get(format!("http://{}/api/tags", settings.ollama_address));
-
-        let request = client.send();
+    pub async fn new(connection: Connection) -> anyhow::Result<Self> {
+        let tags = provider(connection).list_models().await?;
 
-        if let Ok(result) = request {
-            Self {
-                result: result.json::<Tags>(),
-            }
-        } else {
-            Self {
-                result: Ok(Tags { models: Vec::new() }),
-            }
-        }
+        Ok(Self { tags })
     }
 }
@@ -8,6 +8,12 @@ use serde::{Deserialize, Serialize};
 use std::{
     fs::{self, File},
     io::{Read, Write},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::{
+    api::{BotResponse, ChatMessage, Role},
+    context, fl,
 };
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -41,42 +47,544 @@ impl Image {
         let mut buffer = Vec::new();
         file.read_to_end(&mut buffer).expect("Failed to read file");
 
-        let data = bytes::Bytes::from(buffer);
+        Self::from_bytes(buffer)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Self {
+        let data = bytes::Bytes::from(data);
 
         Self {
             base64: BASE64_STANDARD.encode(&data),
             data,
         }
     }
+
+    pub fn from_base64(base64: String) -> anyhow::Result<Self> {
+        let data = bytes::Bytes::from(BASE64_STANDARD.decode(&base64)?);
+
+        Ok(Self { base64, data })
+    }
+
+    pub fn mime(&self) -> &'static str {
+        if self.base64.starts_with("iVBORw0KGgo") {
+            "image/png"
+        } else if self.base64.starts_with("R0lGOD") {
+            "image/gif"
+        } else if self.base64.starts_with("UklGR") {
+            "image/webp"
+        } else if self.is_svg() {
+            "image/svg+xml"
+        } else {
+            "image/jpeg"
+        }
+    }
+
+    /// SVG is text, so it is recognised by its opening tag rather than
+    /// by magic bytes.
+    fn is_svg(&self) -> bool {
+        let head = self.base64.get(..self.base64.len().min(1024) / 4 * 4);
+        let Some(head) = head.and_then(|head| BASE64_STANDARD.decode(head).ok()) else {
+            return false;
+        };
+        let head = String::from_utf8_lossy(&head);
+        let head = head.trim_start_matches('\u{feff}').trim_start();
+
+        head.starts_with("<svg") || (head.starts_with("<?xml") && head.contains("<svg"))
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self.mime() {
+            "image/png" => "png",
+            "image/gif" => "gif",
+            "image/webp" => "webp",
+            "image/svg+xml" => "svg",
+            _ => "jpg",
+        }
+    }
+
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.mime(), self.base64)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Node {
+    pub id: usize,
+    pub parent: Option<usize>,
+    pub text: Text,
+    /// Token count reported by the server, if known.
+    #[serde(default)]
+    pub tokens: Option<u64>,
+    #[serde(default)]
+    pub stats: Option<Stats>,
+    /// The reply was stopped before the model finished it.
+    #[serde(default)]
+    pub stopped: bool,
+}
+
+/// Timings reported by Ollama for a reply. Durations are in nanoseconds,
+/// like on the wire.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Stats {
+    pub total_duration: Option<u64>,
+    pub load_duration: Option<u64>,
+    pub prompt_eval_count: Option<u64>,
+    pub prompt_eval_duration: Option<u64>,
+    pub eval_count: Option<u64>,
+    pub eval_duration: Option<u64>,
+    /// Measured by the applet, from sending the request to the first token.
+    pub time_to_first_token: Option<u64>,
+}
+
+impl Stats {
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        let count = self.eval_count?;
+        let duration = self.eval_duration.filter(|duration| *duration > 0)?;
+
+        Some(count as f64 / (duration as f64 / 1e9))
+    }
+}
+
+impl From<&BotResponse> for Stats {
+    fn from(response: &BotResponse) -> Self {
+        Self {
+            total_duration: response.total_duration,
+            load_duration: response.load_duration,
+            prompt_eval_count: response.prompt_eval_count,
+            prompt_eval_duration: response.prompt_eval_duration,
+            eval_count: response.eval_count,
+            eval_duration: response.eval_duration,
+            time_to_first_token: None,
+        }
+    }
+}
+
+impl Node {
+    pub fn estimated_tokens(&self) -> u64 {
+        self.tokens.unwrap_or(match &self.text {
+            Text::User(MessageContent::Text(text)) | Text::Bot(MessageContent::Text(text)) => {
+                context::estimate(text)
+            }
+            Text::User(MessageContent::Image(_)) | Text::Bot(MessageContent::Image(_)) => {
+                context::IMAGE_TOKENS
+            }
+        })
+    }
+}
+
+/// Condensed version of the start of a branch, sent in place of the
+/// messages it covers.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Summary {
+    /// Last message covered by the summary.
+    pub up_to: usize,
+    pub text: String,
 }
 
-#[derive(Serialize, Deserialize)]
+/// One `/api/chat` message together with its estimated size.
+#[derive(Debug, Clone)]
+pub struct Turn {
+    pub message: ChatMessage,
+    pub tokens: u64,
+    /// Last node folded into this message.
+    pub node: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Metadata {
+    pub title: String,
+    pub created_at: String,
+    /// Saved conversation this one was forked from.
+    pub forked_from: Option<String>,
+}
+
+/// Messages form a tree: editing a prompt or regenerating a reply adds a
+/// sibling instead of replacing anything. The active branch runs from the
+/// root to `leaf` and is what gets shown and sent to the model.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Conversation {
-    pub messages: Vec<Text>,
+    #[serde(default)]
+    pub nodes: Vec<Node>,
+    #[serde(default)]
+    pub leaf: Option<usize>,
+    #[serde(default)]
+    pub summary: Option<Summary>,
+    #[serde(default)]
+    pub metadata: Metadata,
+    /// Name of the file the conversation was loaded from or saved to.
+    #[serde(skip)]
+    pub filename: Option<String>,
+    /// Flat message list written by older versions, turned into nodes on load.
+    #[serde(default, skip_serializing)]
+    messages: Vec<Text>,
+    /// Changes whenever what gets sent to the model may have changed.
+    #[serde(skip)]
+    revision: u64,
+}
+
+/// Revisions are unique across conversations, so that a cache keyed on one
+/// notices when the conversation is replaced.
+fn next_revision() -> u64 {
+    static REVISION: AtomicU64 = AtomicU64::new(1);
+    REVISION.fetch_add(1, Ordering::Relaxed)
 }
 
 impl Conversation {
     pub fn new() -> Self {
         Self {
+            nodes: Vec::new(),
+            leaf: None,
+            summary: None,
+            metadata: Metadata {
+                created_at: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                ..Default::default()
+            },
+            filename: None,
             messages: Vec::new(),
+            revision: next_revision(),
         }
     }
 
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    fn touch(&mut self) {
+        self.revision = next_revision();
+    }
+
+    pub fn set_summary(&mut self, summary: Summary) -> &mut Self {
+        self.summary = Some(summary);
+        self.touch();
+        self
+    }
+
+    /// New, unsaved conversation holding the history up to and including `id`.
+    pub fn fork(&self, id: usize) -> Self {
+        let mut path = Vec::new();
+        let mut current = Some(id);
+
+        while let Some(node) = current.and_then(|id| self.nodes.get(id)) {
+            path.push((node.text.clone(), node.tokens));
+            current = node.parent;
+        }
+
+        let mut fork = Self::new();
+        for (text, tokens) in path.into_iter().rev() {
+            fork.push(text);
+            if let Some(leaf) = fork.leaf {
+                fork.set_tokens(leaf, tokens);
+            }
+        }
+
+        let origin = if self.metadata.title.is_empty() {
+            self.filename.clone().unwrap_or_default()
+        } else {
+            self.metadata.title.clone()
+        };
+        fork.metadata.title = fl!("fork-title", origin = origin);
+        fork.metadata.forked_from = self.filename.clone();
+
+        fork
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Appends a message to the active branch.
     pub fn push(&mut self, message: Text) -> &mut Self {
-        self.messages.push(message);
+        self.touch();
+        let id = self.nodes.len();
+
+        self.nodes.push(Node {
+            id,
+            parent: self.leaf,
+            text: message,
+            tokens: None,
+            stats: None,
+            stopped: false,
+        });
+        self.leaf = Some(id);
         self
     }
 
-    pub fn save_to_file(&self) -> anyhow::Result<()> {
+    /// Nodes of the active branch, from the root to the leaf.
+    pub fn branch(&self) -> Vec<&Node> {
+        let mut branch = Vec::new();
+        let mut current = self.leaf;
+
+        while let Some(node) = current.and_then(|id| self.nodes.get(id)) {
+            branch.push(node);
+            current = node.parent;
+        }
+
+        branch.reverse();
+        branch
+    }
+
+    pub fn messages(&self) -> Vec<&Text> {
+        self.branch().into_iter().map(|node| &node.text).collect()
+    }
+
+    /// Moves the active branch back to just before `id`, so the next pushed
+    /// message becomes a sibling of it.
+    pub fn rewind(&mut self, id: usize) -> &mut Self {
+        self.touch();
+        if let Some(node) = self.nodes.get(id) {
+            self.leaf = node.parent;
+        }
+        self
+    }
+
+    /// Messages sharing a parent with `id`, including itself, oldest first.
+    pub fn siblings(&self, id: usize) -> Vec<usize> {
+        let Some(parent) = self.nodes.get(id).map(|node| node.parent) else {
+            return Vec::new();
+        };
+
+        self.nodes
+            .iter()
+            .filter(|node| node.parent == parent)
+            .map(|node| node.id)
+            .collect()
+    }
+
+    /// Makes `id` part of the active branch, following the latest reply
+    /// below it down to a leaf.
+    pub fn select(&mut self, id: usize) -> &mut Self {
+        if id >= self.nodes.len() {
+            return self;
+        }
+        self.touch();
+
+        let mut current = id;
+        while let Some(child) = self
+            .nodes
+            .iter()
+            .rev()
+            .find(|node| node.parent == Some(current))
+        {
+            current = child.id;
+        }
+
+        self.leaf = Some(current);
+        self
+    }
+
+    pub fn set_tokens(&mut self, id: usize, tokens: Option<u64>) -> &mut Self {
+        self.touch();
+        if let Some(node) = self.nodes.get_mut(id) {
+            node.tokens = tokens;
+        }
+        self
+    }
+
+    pub fn set_stats(&mut self, id: usize, stats: Stats) -> &mut Self {
+        if let Some(node) = self.nodes.get_mut(id) {
+            node.stats = Some(stats);
+        }
+        self
+    }
+
+    pub fn set_stopped(&mut self, id: usize, stopped: bool) -> &mut Self {
+        if let Some(node) = self.nodes.get_mut(id) {
+            node.stopped = stopped;
+        }
+        self
+    }
+
+    /// Adds to the text of a reply, dropping its now outdated token count.
+    pub fn append_text(&mut self, id: usize, text: &str) -> &mut Self {
+        self.touch();
+        if let Some(node) = self.nodes.get_mut(id) {
+            if let Text::Bot(MessageContent::Text(reply)) = &mut node.text {
+                reply.push_str(text);
+                node.tokens = None;
+            }
+        }
+        self
+    }
+
+    /// Sum of the statistics of every reply in the conversation, including
+    /// alternate ones.
+    pub fn total_stats(&self) -> Stats {
+        let add = |total: &mut Option<u64>, value: Option<u64>| {
+            if let Some(value) = value {
+                *total = Some(total.unwrap_or_default() + value);
+            }
+        };
+
+        let mut total = Stats::default();
+        for stats in self.nodes.iter().filter_map(|node| node.stats.as_ref()) {
+            add(&mut total.total_duration, stats.total_duration);
+            add(&mut total.load_duration, stats.load_duration);
+            add(&mut total.prompt_eval_count, stats.prompt_eval_count);
+            add(&mut total.prompt_eval_duration, stats.prompt_eval_duration);
+            add(&mut total.eval_count, stats.eval_count);
+            add(&mut total.eval_duration, stats.eval_duration);
+        }
+
+        total
+    }
+
+    /// The summary, if it covers the start of the active branch.
+    pub fn active_summary(&self) -> Option<&Summary> {
+        let summary = self.summary.as_ref()?;
+
+        self.branch()
+            .iter()
+            .any(|node| node.id == summary.up_to)
+            .then_some(summary)
+    }
+
+    fn migrate(&mut self) {
+        for message in std::mem::take(&mut self.messages) {
+            self.push(message);
+        }
+    }
+
+    /// Builds a conversation from `/api/chat` messages. Images that cannot be
+    /// decoded and system messages are dropped.
+    pub fn from_chat_messages(messages: &[ChatMessage]) -> Self {
+        let mut conversation = Self::new();
+
+        for message in messages {
+            match message.role {
+                Role::User => {
+                    for image in &message.images {
+                        if let Ok(image) = Image::from_base64(image.clone()) {
+                            conversation.push(Text::User(MessageContent::Image(
+                                ImageAttachment::Raster(image),
+                            )));
+                        }
+                    }
+                    if !message.content.is_empty() {
+                        conversation
+                            .push(Text::User(MessageContent::Text(message.content.clone())));
+                    }
+                }
+                Role::Assistant => {
+                    conversation.push(Text::Bot(MessageContent::Text(message.content.clone())));
+                }
+                Role::System => {}
+            }
+        }
+
+        conversation
+    }
+
+    /// Stable FNV-1a hash of the conversation content, used to recognise
+    /// conversations that were already saved.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+
+        for message in self.chat_messages() {
+            let role = match message.role {
+                Role::System => "system",
+                Role::User => "user",
+                Role::Assistant => "assistant",
+            };
+            let parts = [role, message.content.trim()]
+                .into_iter()
+                .chain(message.images.iter().map(String::as_str));
+
+            for part in parts {
+                for byte in part.bytes().chain(std::iter::once(0)) {
+                    hash ^= byte as u64;
+                    hash = hash.wrapping_mul(0x100000001b3);
+                }
+            }
+        }
+
+        hash
+    }
+
+    /// Messages in the shape of the Ollama `/api/chat` schema, with image
+    /// attachments folded into the user message that follows them.
+    pub fn chat_messages(&self) -> Vec<ChatMessage> {
+        self.turns().into_iter().map(|turn| turn.message).collect()
+    }
+
+    pub fn turns(&self) -> Vec<Turn> {
+        let mut turns = Vec::new();
+        let mut images = Vec::new();
+        let mut image_tokens = 0;
+
+        for node in self.branch() {
+            let (message, tokens) = match &node.text {
+                Text::User(MessageContent::Image(ImageAttachment::Raster(image)))
+                | Text::User(MessageContent::Image(ImageAttachment::Svg(image))) => {
+                    images.push(image.base64.clone());
+                    image_tokens += node.estimated_tokens();
+                    continue;
+                }
+                Text::User(MessageContent::Text(text)) => (
+                    ChatMessage {
+                        role: Role::User,
+                        content: text.clone(),
+                        images: std::mem::take(&mut images),
+                    },
+                    node.estimated_tokens() + std::mem::take(&mut image_tokens),
+                ),
+                Text::Bot(MessageContent::Text(text)) => (
+                    ChatMessage {
+                        role: Role::Assistant,
+                        content: text.clone(),
+                        images: Vec::new(),
+                    },
+                    node.estimated_tokens(),
+                ),
+                Text::Bot(MessageContent::Image(_)) => continue,
+            };
+
+            turns.push(Turn {
+                message,
+                tokens,
+                node: node.id,
+            });
+        }
+
+        if !images.is_empty() {
+            turns.push(Turn {
+                message: ChatMessage {
+                    role: Role::User,
+                    content: String::new(),
+                    images,
+                },
+                tokens: image_tokens,
+                node: self.leaf.unwrap_or_default(),
+            });
+        }
+
+        turns
+    }
+
+    /// Saves over the file the conversation came from, or into a new one
+    /// named after the current time.
+    pub fn save_to_file(&mut self) -> anyhow::Result<()> {
+        let filename = match &self.filename {
+            Some(filename) => filename.clone(),
+            None => {
+                let now = Local::now();
+                unique_name(&now.format("%Y-%m-%d %H:%M:%S").to_string())
+            }
+        };
+
+        self.save_as(&filename)?;
+        self.filename = Some(filename);
+
+        Ok(())
+    }
+
+    pub fn save_as(&self, name: &str) -> anyhow::Result<()> {
         let data_path = dirs::data_dir()
             .expect("xdg-data not found")
             .join("cosmic-ext-applet-ollama/chat");
 
         fs::create_dir_all(&data_path)?;
 
-        let now = Local::now();
-        let formatted = now.format("%Y-%m-%d %H:%M:%S").to_string();
-        let filename = format!("{}.ron", formatted);
+        let filename = format!("{}.ron", name);
 
         let pretty = PrettyConfig::default();
         let ron_string = to_string_pretty(self, pretty).unwrap();
@@ -87,7 +595,7 @@ impl Conversation {
         Ok(())
     }
 
-    pub fn remove(&self, filename: String) -> anyhow::Result<()> {
+    pub fn remove(&mut self, filename: String) -> anyhow::Result<()> {
         let data_path = dirs::data_dir()
             .expect("xdg-data not found")
             .join("cosmic-ext-applet-ollama/chat")
@@ -95,10 +603,30 @@ impl Conversation {
 
         fs::remove_file(data_path)?;
 
+        if self.filename.as_ref() == Some(&filename) {
+            self.filename = None;
+        }
+
         Ok(())
     }
 }
 
+/// `name`, or `name (2)` and so on when a conversation of that name exists.
+pub fn unique_name(name: &str) -> String {
+    let data_path = dirs::data_dir()
+        .expect("xdg-data not found")
+        .join("cosmic-ext-applet-ollama/chat");
+
+    let mut candidate = name.to_string();
+    let mut counter = 1;
+    while data_path.join(format!("{}.ron", candidate)).exists() {
+        counter += 1;
+        candidate = format!("{} ({})", name, counter);
+    }
+
+    candidate
+}
+
 pub fn read_conversation_files() -> anyhow::Result<Vec<String>> {
     let data_path = dirs::data_dir()
         .expect("xdg-data not found")
@@ -123,12 +651,16 @@ pub fn read_conversation_files() -> anyhow::Result<Vec<String>> {
     Ok(conversations)
 }
 
-pub fn load_conversation(filename: String) -> Conversation {
+pub fn load_conversation(filename: String) -> anyhow::Result<Conversation> {
     let data_path = dirs::data_dir()
         .expect("xdg-data not found")
         .join("cosmic-ext-applet-ollama/chat")
         .join(format!("{}.ron", filename));
 
-    let contents = fs::read_to_string(data_path).unwrap();
-    from_str(&contents).unwrap()
+    let contents = fs::read_to_string(data_path)?;
+    let mut conversation: Conversation = from_str(&contents)?;
+    conversation.migrate();
+    conversation.filename = Some(filename);
+    conversation.touch();
+    Ok(conversation)
 }
@@ -0,0 +1,213 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::{ChatMessage, Completion, Connection, Parameters, Role},
+    chat::{Stats, Summary, Turn},
+    fl, usage,
+};
+
+/// Rough token cost of an attached image for llava-style models.
+pub const IMAGE_TOKENS: u64 = 576;
+
+/// Part of the context window left free for the reply, as a divisor.
+const REPLY_SHARE: u64 = 4;
+
+const SUMMARY_PROMPT: &str = "Summarize the following conversation between a user and an \
+    assistant. Keep every fact, decision, name, number and piece of code that later messages \
+    may rely on. Answer with the summary only.";
+
+/// What to do once the history no longer fits into the model's context.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Strategy {
+    /// Drop the oldest turns until the rest fits.
+    Truncate,
+    /// Fold the oldest turns into a pinned summary.
+    #[default]
+    Summarize,
+    /// Keep only the most recent turns.
+    SlidingWindow,
+}
+
+impl Strategy {
+    pub const ALL: [Strategy; 3] = [
+        Strategy::Truncate,
+        Strategy::Summarize,
+        Strategy::SlidingWindow,
+    ];
+
+    pub fn name(&self) -> String {
+        match self {
+            Strategy::Truncate => fl!("strategy-truncate"),
+            Strategy::Summarize => fl!("strategy-summarize"),
+            Strategy::SlidingWindow => fl!("strategy-sliding-window"),
+        }
+    }
+}
+
+pub fn estimate(text: &str) -> u64 {
+    (text.chars().count() as u64).div_ceil(4)
+}
+
+/// Tokens available for the history.
+pub fn budget(context_length: u64) -> u64 {
+    context_length - context_length / REPLY_SHARE
+}
+
+fn summary_turn(summary: &Summary) -> Turn {
+    Turn {
+        message: ChatMessage {
+            role: Role::System,
+            content: format!("Summary of the earlier conversation:\n\n{}", summary.text),
+            images: Vec::new(),
+        },
+        tokens: estimate(&summary.text),
+        node: summary.up_to,
+    }
+}
+
+/// Turns that are not covered by the summary.
+fn unsummarized(turns: Vec<Turn>, summary: Option<&Summary>) -> Vec<Turn> {
+    match summary {
+        // Node ids only grow along a branch.
+        Some(summary) => turns
+            .into_iter()
+            .filter(|turn| turn.node > summary.up_to)
+            .collect(),
+        None => turns,
+    }
+}
+
+/// Drops turns from the front until the total fits, always keeping the
+/// latest one.
+fn truncate(mut turns: Vec<Turn>, budget: u64) -> Vec<Turn> {
+    let mut total: u64 = turns.iter().map(|turn| turn.tokens).sum();
+
+    while total > budget && turns.len() > 1 {
+        total -= turns.remove(0).tokens;
+    }
+
+    turns
+}
+
+/// The history to send: the summary if there is one, followed by as many
+/// turns as the strategy allows within the model's context.
+pub fn fit(
+    turns: Vec<Turn>,
+    summary: Option<&Summary>,
+    strategy: Strategy,
+    window: usize,
+    context_length: Option<u64>,
+) -> Vec<Turn> {
+    let mut turns = unsummarized(turns, summary);
+    let summary = summary.map(summary_turn);
+
+    if let Some(context_length) = context_length {
+        let budget = budget(context_length)
+            .saturating_sub(summary.as_ref().map_or(0, |summary| summary.tokens));
+        let total: u64 = turns.iter().map(|turn| turn.tokens).sum();
+
+        if total > budget {
+            if strategy == Strategy::SlidingWindow {
+                let start = turns.len().saturating_sub(window.max(1));
+                turns.drain(..start);
+            }
+            turns = truncate(turns, budget);
+        }
+    }
+
+    summary.into_iter().chain(turns).collect()
+}
+
+/// The oldest unsummarized turns that should be folded into the summary
+/// before the next request, if any. What stays behind takes at most
+/// half of the budget, so summarizing does not have to happen every turn.
+pub fn to_summarize(
+    turns: &[Turn],
+    summary: Option<&Summary>,
+    context_length: Option<u64>,
+) -> Option<Vec<Turn>> {
+    let context_length = context_length?;
+    let turns = unsummarized(turns.to_vec(), summary);
+
+    let summary_tokens = summary.map_or(0, |summary| estimate(&summary.text));
+    let total: u64 = turns.iter().map(|turn| turn.tokens).sum::<u64>() + summary_tokens;
+    if total <= budget(context_length) {
+        return None;
+    }
+
+    let keep_budget = budget(context_length) / 2;
+    let mut kept = 0;
+    let mut keep_from = turns.len();
+
+    for (index, turn) in turns.iter().enumerate().rev() {
+        if kept + turn.tokens > keep_budget && keep_from < turns.len() {
+            break;
+        }
+        kept += turn.tokens;
+        keep_from = index;
+    }
+
+    (keep_from > 0).then(|| turns[..keep_from].to_vec())
+}
+
+/// Asks the model for a summary of `turns`, building on the previous one.
+pub async fn summarize(
+    connection: Connection,
+    model: String,
+    previous: Option<String>,
+    turns: Vec<Turn>,
+    keep_alive: String,
+) -> anyhow::Result<Summary> {
+    let up_to = turns
+        .last()
+        .map(|turn| turn.node)
+        .ok_or_else(|| anyhow::anyhow!("nothing to summarize"))?;
+
+    let mut transcript = String::new();
+    if let Some(previous) = previous {
+        transcript.push_str(&format!("Summary so far:\n{}\n\n", previous));
+    }
+    for turn in turns {
+        let speaker = match turn.message.role {
+            Role::System => "System",
+            Role::User => "User",
+            Role::Assistant => "Assistant",
+        };
+        transcript.push_str(&format!("{}: {}\n\n", speaker, turn.message.content));
+    }
+
+    let messages = vec![
+        ChatMessage {
+            role: Role::System,
+            content: SUMMARY_PROMPT.to_string(),
+            images: Vec::new(),
+        },
+        ChatMessage {
+            role: Role::User,
+            content: transcript,
+            images: Vec::new(),
+        },
+    ];
+
+    let parameters = Parameters {
+        keep_alive,
+        ..Default::default()
+    };
+    let completion = Completion::new(connection, model.clone(), messages, parameters).await?;
+    let _ = usage::append(&usage::Record::new(
+        &model,
+        &Stats::from(&completion.response),
+    ));
+
+    let text = completion
+        .response
+        .message
+        .map(|message| message.content)
+        .unwrap_or_default();
+
+    if text.trim().is_empty() {
+        anyhow::bail!("the model returned an empty summary");
+    }
+
+    Ok(Summary { up_to, text })
+}
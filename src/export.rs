@@ -0,0 +1,290 @@
+use chrono::Local;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    chat::{
+        load_conversation, read_conversation_files, Conversation, Image, ImageAttachment,
+        MessageContent, Text,
+    },
+    fl,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Markdown,
+    Html,
+    Json,
+    PlainText,
+}
+
+impl Format {
+    pub const ALL: [Format; 4] = [
+        Format::Markdown,
+        Format::Html,
+        Format::Json,
+        Format::PlainText,
+    ];
+
+    pub fn name(&self) -> String {
+        match self {
+            Format::Markdown => fl!("format-markdown"),
+            Format::Html => fl!("format-html"),
+            Format::Json => fl!("format-json"),
+            Format::PlainText => fl!("format-plain-text"),
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Markdown => "md",
+            Format::Html => "html",
+            Format::Json => "json",
+            Format::PlainText => "txt",
+        }
+    }
+
+    pub fn glob(&self) -> String {
+        format!("*.{}", self.extension())
+    }
+}
+
+enum Images {
+    Embedded,
+    Linked { dir: PathBuf, prefix: String },
+}
+
+pub fn default_name() -> String {
+    Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// Saved conversations are named after their timestamp, which is not a
+/// portable file name.
+pub fn file_name(name: &str, format: Format) -> String {
+    format!("{}.{}", name.replace([':', '/'], "-"), format.extension())
+}
+
+pub fn write(
+    conversation: &Conversation,
+    format: Format,
+    path: &Path,
+    embed_images: bool,
+) -> anyhow::Result<()> {
+    let contents = match format {
+        Format::Markdown => {
+            let images = if embed_images {
+                Images::Embedded
+            } else {
+                let stem = path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let prefix = format!("{}_images", stem);
+
+                Images::Linked {
+                    dir: path.with_file_name(&prefix),
+                    prefix,
+                }
+            };
+
+            markdown(conversation, &images)?
+        }
+        Format::Html => html(conversation),
+        Format::Json => serde_json::to_string_pretty(&conversation.chat_messages())?,
+        Format::PlainText => plain_text(conversation),
+    };
+
+    fs::write(path, contents)?;
+
+    Ok(())
+}
+
+/// Exports every readable saved conversation into `dir`, returning how many
+/// were written.
+pub fn write_all(format: Format, dir: &Path, embed_images: bool) -> anyhow::Result<usize> {
+    let mut written = 0;
+
+    for name in read_conversation_files()? {
+        let Ok(conversation) = load_conversation(name.clone()) else {
+            continue;
+        };
+        write(
+            &conversation,
+            format,
+            &dir.join(file_name(&name, format)),
+            embed_images,
+        )?;
+        written += 1;
+    }
+
+    Ok(written)
+}
+
+fn image_of(attachment: &ImageAttachment) -> &Image {
+    match attachment {
+        ImageAttachment::Svg(image) | ImageAttachment::Raster(image) => image,
+    }
+}
+
+fn markdown(conversation: &Conversation, images: &Images) -> anyhow::Result<String> {
+    let mut output = String::new();
+    let mut image_count = 0;
+
+    for message in conversation.messages() {
+        let (heading, content) = match message {
+            Text::User(content) => (fl!("export-user"), content),
+            Text::Bot(content) => (fl!("export-assistant"), content),
+        };
+
+        output.push_str(&format!("## {}\n\n", heading));
+
+        match content {
+            MessageContent::Text(text) => output.push_str(text.trim_end()),
+            MessageContent::Image(attachment) => {
+                let image = image_of(attachment);
+                image_count += 1;
+
+                let target = match images {
+                    Images::Embedded => image.data_url(),
+                    Images::Linked { dir, prefix } => {
+                        fs::create_dir_all(dir)?;
+                        let name = format!("{}.{}", image_count, image.extension());
+                        fs::write(dir.join(&name), base64_decode(&image.base64)?)?;
+                        // The prefix carries the timestamp's spaces, which a
+                        // bare link destination may not contain.
+                        format!("<{}/{}>", prefix, name)
+                    }
+                };
+
+                output.push_str(&format!("![image {}]({})", image_count, target));
+            }
+        }
+
+        output.push_str("\n\n");
+    }
+
+    Ok(output)
+}
+
+fn html(conversation: &Conversation) -> String {
+    let mut body = String::new();
+
+    for message in conversation.messages() {
+        let (class, heading, content) = match message {
+            Text::User(content) => ("user", fl!("export-user"), content),
+            Text::Bot(content) => ("assistant", fl!("export-assistant"), content),
+        };
+
+        body.push_str(&format!(
+            "<section class=\"{}\">\n<h2>{}</h2>\n",
+            class,
+            escape(&heading)
+        ));
+
+        match content {
+            MessageContent::Text(text) => body.push_str(&html_text(text)),
+            MessageContent::Image(attachment) => body.push_str(&format!(
+                "<img src=\"{}\" alt=\"image\">\n",
+                image_of(attachment).data_url()
+            )),
+        }
+
+        body.push_str("</section>\n");
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n\
+         body {{ font-family: sans-serif; max-width: 48em; margin: 2em auto; padding: 0 1em; }}\n\
+         section {{ margin-bottom: 1.5em; }}\n\
+         section.user h2 {{ color: #3f6fb0; }}\n\
+         section.assistant h2 {{ color: #4a8c5c; }}\n\
+         pre {{ background: #f0f0f0; padding: 0.75em; overflow-x: auto; }}\n\
+         img {{ max-width: 100%; }}\n\
+         </style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape(&fl!("window-title")),
+        body
+    )
+}
+
+/// Paragraphs and fenced code blocks are enough to keep chat output readable.
+fn html_text(text: &str) -> String {
+    let mut output = String::new();
+    let mut paragraph: Vec<String> = Vec::new();
+    let mut in_code = false;
+
+    let flush = |output: &mut String, paragraph: &mut Vec<String>| {
+        if !paragraph.is_empty() {
+            output.push_str(&format!("<p>{}</p>\n", paragraph.join("<br>\n")));
+            paragraph.clear();
+        }
+    };
+
+    for line in text.lines() {
+        if let Some(language) = line.trim_start().strip_prefix("```") {
+            if in_code {
+                output.push_str("</code></pre>\n");
+            } else {
+                flush(&mut output, &mut paragraph);
+                let language = language.trim();
+                if language.is_empty() {
+                    output.push_str("<pre><code>");
+                } else {
+                    output.push_str(&format!(
+                        "<pre><code class=\"language-{}\">",
+                        escape(language)
+                    ));
+                }
+            }
+            in_code = !in_code;
+        } else if in_code {
+            output.push_str(&escape(line));
+            output.push('\n');
+        } else if line.trim().is_empty() {
+            flush(&mut output, &mut paragraph);
+        } else {
+            paragraph.push(escape(line));
+        }
+    }
+
+    if in_code {
+        output.push_str("</code></pre>\n");
+    }
+    flush(&mut output, &mut paragraph);
+
+    output
+}
+
+fn plain_text(conversation: &Conversation) -> String {
+    let mut output = String::new();
+
+    for message in conversation.messages() {
+        let (speaker, content) = match message {
+            Text::User(content) => (fl!("export-user"), content),
+            Text::Bot(content) => (fl!("export-assistant"), content),
+        };
+
+        let text = match content {
+            MessageContent::Text(text) => text.trim_end().to_string(),
+            MessageContent::Image(_) => fl!("export-image"),
+        };
+
+        output.push_str(&format!("{}:\n{}\n\n", speaker, text));
+    }
+
+    output
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn base64_decode(data: &str) -> anyhow::Result<Vec<u8>> {
+    use base64::prelude::*;
+
+    Ok(BASE64_STANDARD.decode(data)?)
+}
@@ -0,0 +1,349 @@
+use base64::prelude::*;
+use chrono::Local;
+use serde_json::Value;
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    api::{ChatMessage, Role},
+    chat::{load_conversation, read_conversation_files, unique_name, Conversation},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    OpenWebUi,
+    ChatGpt,
+    OllamaChat,
+}
+
+impl Source {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Source::OpenWebUi => "Open WebUI",
+            Source::ChatGpt => "ChatGPT",
+            Source::OllamaChat => "Ollama",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Imported {
+    pub title: String,
+    pub conversation: Conversation,
+}
+
+/// Result of a dry run: what would be written, and what was left out.
+#[derive(Debug, Clone)]
+pub struct Preview {
+    pub source: Source,
+    pub conversations: Vec<Imported>,
+    pub messages: usize,
+    pub images: usize,
+    pub duplicates: usize,
+}
+
+pub fn preview(path: &Path) -> anyhow::Result<Preview> {
+    let contents = fs::read_to_string(path)?;
+    let json: Value = serde_json::from_str(&contents)?;
+    let base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+    let (source, parsed) = parse(&json, &base_dir)?;
+
+    // Files that cannot be read are left out rather than failing the import.
+    let mut known: HashSet<u64> = read_conversation_files()?
+        .into_iter()
+        .filter_map(|name| load_conversation(name).ok())
+        .map(|conversation| conversation.fingerprint())
+        .collect();
+
+    let mut conversations = Vec::new();
+    let mut duplicates = 0;
+
+    for (title, messages) in parsed {
+        let conversation = Conversation::from_chat_messages(&messages);
+        if conversation.is_empty() {
+            continue;
+        }
+
+        if known.insert(conversation.fingerprint()) {
+            conversations.push(Imported {
+                title,
+                conversation,
+            });
+        } else {
+            duplicates += 1;
+        }
+    }
+
+    let messages = conversations
+        .iter()
+        .map(|imported| imported.conversation.chat_messages().len())
+        .sum();
+    let images = conversations
+        .iter()
+        .flat_map(|imported| imported.conversation.chat_messages())
+        .map(|message| message.images.len())
+        .sum();
+
+    Ok(Preview {
+        source,
+        conversations,
+        messages,
+        images,
+        duplicates,
+    })
+}
+
+/// Longest title used in a file name, in bytes; file names are limited to
+/// 255.
+const TITLE_LENGTH: usize = 120;
+
+/// What an import wrote, and the names it could not write with the reason.
+#[derive(Debug, Clone, Default)]
+pub struct Committed {
+    pub written: usize,
+    pub failed: Vec<(String, String)>,
+}
+
+/// Saves every conversation of the preview under a new name, going on past
+/// the ones that cannot be written.
+pub fn commit(preview: &Preview) -> Committed {
+    let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let mut committed = Committed::default();
+
+    for imported in &preview.conversations {
+        let title = imported.title.trim();
+        let name = if title.is_empty() {
+            format!("{} import", timestamp)
+        } else {
+            let title = title.replace(['/', '\\', '\0'], "-");
+            format!(
+                "{} {}",
+                timestamp,
+                truncate(&title, TITLE_LENGTH).trim_end()
+            )
+        };
+
+        let mut conversation = imported.conversation.clone();
+        conversation.metadata.title = title.to_string();
+        match conversation.save_as(&unique_name(&name)) {
+            Ok(()) => committed.written += 1,
+            Err(why) => committed.failed.push((name, why.to_string())),
+        }
+    }
+
+    committed
+}
+
+/// At most `length` bytes of `text`, cut at a character boundary.
+fn truncate(text: &str, length: usize) -> &str {
+    let mut end = length.min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+type Parsed = Vec<(String, Vec<ChatMessage>)>;
+
+fn parse(json: &Value, base_dir: &Path) -> anyhow::Result<(Source, Parsed)> {
+    let entries: Vec<&Value> = match json {
+        Value::Array(entries) => entries.iter().collect(),
+        entry => vec![entry],
+    };
+
+    let Some(first) = entries.first() else {
+        anyhow::bail!("file contains no conversations");
+    };
+
+    if first.get("mapping").is_some() {
+        let parsed = entries
+            .iter()
+            .map(|entry| (title(entry), chatgpt_messages(entry, base_dir)))
+            .collect();
+        Ok((Source::ChatGpt, parsed))
+    } else if first.get("chat").is_some() {
+        let parsed = entries
+            .iter()
+            .map(|entry| (title(entry), open_webui_messages(&entry["chat"])))
+            .collect();
+        Ok((Source::OpenWebUi, parsed))
+    } else if first.get("role").is_some() {
+        Ok((
+            Source::OllamaChat,
+            vec![(String::new(), chat_messages(&entries))],
+        ))
+    } else if let Some(Value::Array(messages)) = first.get("messages") {
+        let messages: Vec<&Value> = messages.iter().collect();
+        Ok((
+            Source::OllamaChat,
+            vec![(title(first), chat_messages(&messages))],
+        ))
+    } else {
+        anyhow::bail!("unrecognised conversation format")
+    }
+}
+
+fn title(entry: &Value) -> String {
+    entry
+        .get("title")
+        .or_else(|| entry.get("chat").and_then(|chat| chat.get("title")))
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn role(role: &str) -> Option<Role> {
+    match role {
+        "system" => Some(Role::System),
+        "user" => Some(Role::User),
+        "assistant" => Some(Role::Assistant),
+        _ => None,
+    }
+}
+
+/// Plain `/api/chat` messages, as sent to and returned by Ollama.
+fn chat_messages(entries: &[&Value]) -> Vec<ChatMessage> {
+    entries
+        .iter()
+        .filter_map(|entry| serde_json::from_value::<ChatMessage>((*entry).clone()).ok())
+        .collect()
+}
+
+/// Open WebUI keeps the active branch in `messages`, and the whole tree in
+/// `history`, which older exports lack.
+fn open_webui_messages(chat: &Value) -> Vec<ChatMessage> {
+    let mut path: Vec<&Value> = match chat.get("messages") {
+        Some(Value::Array(messages)) => messages.iter().collect(),
+        _ => Vec::new(),
+    };
+
+    if path.is_empty() {
+        let history = &chat["history"];
+        let mut current = history["currentId"].as_str();
+
+        while let Some(id) = current {
+            let message = &history["messages"][id];
+            if message.is_null() {
+                break;
+            }
+            path.push(message);
+            current = message["parentId"].as_str();
+        }
+        path.reverse();
+    }
+
+    path.into_iter()
+        .filter_map(|message| {
+            let role = role(message["role"].as_str()?)?;
+
+            let mut images: Vec<String> = message["images"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .map(strip_data_url)
+                .collect();
+
+            images.extend(
+                message["files"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter(|file| file["type"].as_str() == Some("image"))
+                    .filter_map(|file| file["url"].as_str())
+                    .filter(|url| url.starts_with("data:"))
+                    .map(strip_data_url),
+            );
+
+            Some(ChatMessage {
+                role,
+                content: message["content"].as_str().unwrap_or_default().to_string(),
+                images,
+            })
+        })
+        .collect()
+}
+
+/// ChatGPT stores each conversation as a tree in `mapping`; the visible
+/// thread is the path from `current_node` back to the root. Images are only
+/// referenced by asset pointer and live next to `conversations.json`.
+fn chatgpt_messages(entry: &Value, base_dir: &Path) -> Vec<ChatMessage> {
+    let mapping = &entry["mapping"];
+    let mut nodes = Vec::new();
+    let mut current = entry["current_node"].as_str();
+
+    while let Some(id) = current {
+        let node = &mapping[id];
+        if node.is_null() {
+            break;
+        }
+        nodes.push(node);
+        current = node["parent"].as_str();
+    }
+    nodes.reverse();
+
+    nodes
+        .into_iter()
+        .filter_map(|node| {
+            let message = &node["message"];
+            let role = role(message["author"]["role"].as_str()?)?;
+
+            let mut content = Vec::new();
+            let mut images = Vec::new();
+
+            for part in message["content"]["parts"].as_array().into_iter().flatten() {
+                match part {
+                    Value::String(text) if !text.is_empty() => content.push(text.clone()),
+                    Value::Object(_) => {
+                        if let Some(image) = part["asset_pointer"]
+                            .as_str()
+                            .and_then(|pointer| chatgpt_asset(pointer, base_dir))
+                        {
+                            images.push(image);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            if content.is_empty() && images.is_empty() {
+                return None;
+            }
+
+            Some(ChatMessage {
+                role,
+                content: content.join("\n\n"),
+                images,
+            })
+        })
+        .collect()
+}
+
+fn chatgpt_asset(pointer: &str, base_dir: &Path) -> Option<String> {
+    let id = pointer
+        .strip_prefix("file-service://")
+        .or_else(|| pointer.strip_prefix("sediment://"))?;
+
+    let path: PathBuf = fs::read_dir(base_dir)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(id))
+        })?;
+
+    fs::read(path).ok().map(|data| BASE64_STANDARD.encode(data))
+}
+
+fn strip_data_url(url: &str) -> String {
+    match url.split_once(";base64,") {
+        Some((_, data)) => data.to_string(),
+        None => url.to_string(),
+    }
+}
@@ -0,0 +1,159 @@
+use cosmic::iced::keyboard::{key::Named, Key, Modifiers};
+use serde::{Deserialize, Serialize};
+
+use crate::fl;
+
+/// What a key binding inside the popup does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum KeyAction {
+    /// Stops the reply, or closes the popup when there is none.
+    Stop,
+    NewChat,
+    ClearChat,
+    SaveConversation,
+    Settings,
+    /// Edits the last prompt while the prompt field is empty.
+    EditLast,
+    CopyLastReply,
+}
+
+impl KeyAction {
+    pub const ALL: [KeyAction; 7] = [
+        KeyAction::Stop,
+        KeyAction::NewChat,
+        KeyAction::ClearChat,
+        KeyAction::SaveConversation,
+        KeyAction::Settings,
+        KeyAction::EditLast,
+        KeyAction::CopyLastReply,
+    ];
+
+    pub fn name(&self) -> String {
+        match self {
+            KeyAction::Stop => fl!("key-stop"),
+            KeyAction::NewChat => fl!("key-new-chat"),
+            KeyAction::ClearChat => fl!("key-clear-chat"),
+            KeyAction::SaveConversation => fl!("key-save-conversation"),
+            KeyAction::Settings => fl!("key-settings"),
+            KeyAction::EditLast => fl!("key-edit-last"),
+            KeyAction::CopyLastReply => fl!("key-copy-last-reply"),
+        }
+    }
+
+    pub fn default_binding(&self) -> &'static str {
+        match self {
+            KeyAction::Stop => "Escape",
+            KeyAction::NewChat => "Ctrl+N",
+            KeyAction::ClearChat => "Ctrl+L",
+            KeyAction::SaveConversation => "Ctrl+S",
+            KeyAction::Settings => "Ctrl+,",
+            KeyAction::EditLast => "Up",
+            KeyAction::CopyLastReply => "Ctrl+Shift+C",
+        }
+    }
+}
+
+/// A binding as written in the settings, e.g. "Ctrl+Shift+C".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    modifiers: Modifiers,
+    key: BindingKey,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum BindingKey {
+    /// Lowercase, so that letters compare case-insensitively.
+    Character(String),
+    Named(Named),
+}
+
+impl Binding {
+    /// Modifiers have to match exactly.
+    pub fn matches(&self, key: &Key, modifiers: Modifiers) -> bool {
+        if self.modifiers != modifiers {
+            return false;
+        }
+
+        match (key, &self.key) {
+            (Key::Character(character), BindingKey::Character(name)) => {
+                character.to_lowercase() == *name
+            }
+            (Key::Named(named), BindingKey::Named(name)) => named == name,
+            _ => false,
+        }
+    }
+}
+
+/// Parses a binding; a single character needs a modifier other than Shift,
+/// or it would be taken away from typing.
+pub fn parse(binding: &str) -> Option<Binding> {
+    let mut parts: Vec<&str> = binding.split('+').map(str::trim).collect();
+    // "Ctrl++" binds the plus key.
+    if binding.trim_end().ends_with("++") {
+        parts.pop();
+        parts.pop();
+        parts.push("+");
+    }
+    let name = parts.pop().filter(|name| !name.is_empty())?;
+
+    let mut modifiers = Modifiers::empty();
+    for part in parts {
+        modifiers |= match part.to_lowercase().as_str() {
+            "ctrl" | "control" => Modifiers::CTRL,
+            "shift" => Modifiers::SHIFT,
+            "alt" => Modifiers::ALT,
+            "super" | "logo" => Modifiers::LOGO,
+            _ => return None,
+        };
+    }
+
+    let key = match named_key(name) {
+        Some(named) => BindingKey::Named(named),
+        None if name.chars().count() == 1 => {
+            if modifiers.difference(Modifiers::SHIFT).is_empty() {
+                return None;
+            }
+            BindingKey::Character(name.to_lowercase())
+        }
+        None => return None,
+    };
+
+    Some(Binding { modifiers, key })
+}
+
+/// Whether a binding such as "Ctrl+Shift+C" describes the pressed key.
+pub fn matches(binding: &str, key: &Key, modifiers: Modifiers) -> bool {
+    parse(binding).is_some_and(|binding| binding.matches(key, modifiers))
+}
+
+fn named_key(name: &str) -> Option<Named> {
+    Some(match name.to_lowercase().as_str() {
+        "escape" | "esc" => Named::Escape,
+        "enter" | "return" => Named::Enter,
+        "tab" => Named::Tab,
+        "space" => Named::Space,
+        "backspace" => Named::Backspace,
+        "delete" => Named::Delete,
+        "up" => Named::ArrowUp,
+        "down" => Named::ArrowDown,
+        "left" => Named::ArrowLeft,
+        "right" => Named::ArrowRight,
+        "home" => Named::Home,
+        "end" => Named::End,
+        "pageup" => Named::PageUp,
+        "pagedown" => Named::PageDown,
+        "f1" => Named::F1,
+        "f2" => Named::F2,
+        "f3" => Named::F3,
+        "f4" => Named::F4,
+        "f5" => Named::F5,
+        "f6" => Named::F6,
+        "f7" => Named::F7,
+        "f8" => Named::F8,
+        "f9" => Named::F9,
+        "f10" => Named::F10,
+        "f11" => Named::F11,
+        "f12" => Named::F12,
+        _ => return None,
+    })
+}
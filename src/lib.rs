@@ -1,14 +1,25 @@
 mod api;
 mod chat;
+mod context;
+mod export;
+mod import;
+mod keybindings;
 mod localize;
 mod models;
+mod openai;
+mod secrets;
+mod shortcuts;
 mod stream;
+mod templates;
+mod usage;
 mod window;
 
 use cosmic::widget;
+use keybindings::KeyAction;
 use ron::de::from_reader;
 use ron::ser::{to_string_pretty, PrettyConfig};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io::{BufReader, Write};
 use std::path::PathBuf;
@@ -19,13 +30,97 @@ pub fn run() -> cosmic::iced::Result {
     cosmic::applet::run::<Window>(())
 }
 
+/// A named Ollama server, with the model and keep-alive used on it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerProfile {
+    /// Stable key of the profile's credentials in the keyring.
+    #[serde(default)]
+    id: String,
+    name: String,
+    #[serde(default)]
+    backend: api::Backend,
+    /// Either `host:port` or a full base URL such as `https://host/ollama`.
+    address: String,
+    model: String,
+    keep_alive: String,
+    /// Extra PEM or DER certificate to trust, for self-signed proxies.
+    #[serde(default)]
+    ca_certificate: PathBuf,
+    /// Models listed first in the picker.
+    #[serde(default)]
+    favorites: Vec<String>,
+}
+
+impl ServerProfile {
+    pub fn new(name: String) -> Self {
+        Self {
+            id: new_profile_id(),
+            name,
+            backend: api::Backend::default(),
+            address: "localhost:11434".to_string(),
+            model: String::new(),
+            keep_alive: "5m".into(),
+            ca_certificate: PathBuf::new(),
+            favorites: Vec::new(),
+        }
+    }
+
+    /// The profile every installation starts with; its id is fixed so that
+    /// it does not change before the settings are first saved.
+    fn local() -> Self {
+        Self {
+            id: "local".into(),
+            ..Self::new("Local".into())
+        }
+    }
+}
+
+/// Defaults applied whenever a model is selected.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelPreset {
+    #[serde(default)]
+    system_prompt: String,
+    #[serde(default)]
+    options: api::Options,
+    /// Overrides the server profile's keep-alive.
+    #[serde(default)]
+    keep_alive: Option<String>,
+    #[serde(default)]
+    think: Option<bool>,
+}
+
+fn new_profile_id() -> String {
+    chrono::Local::now().format("%Y%m%d%H%M%S%f").to_string()
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Settings {
     avatar: PathBuf,
     keep_context: bool,
+    /// Single-server fields of older versions, moved into `servers` on load.
+    #[serde(default, skip_serializing)]
     model: String,
+    #[serde(default, skip_serializing)]
     ollama_address: String,
+    #[serde(default, skip_serializing)]
     keep_alive_model: String,
+    #[serde(default)]
+    servers: Vec<ServerProfile>,
+    #[serde(default)]
+    active_server: usize,
+    #[serde(default)]
+    context_strategy: context::Strategy,
+    #[serde(default = "default_context_window")]
+    context_window: usize,
+    #[serde(default)]
+    presets: HashMap<String, ModelPreset>,
+    /// Key bindings changed from their defaults.
+    #[serde(default)]
+    key_bindings: HashMap<KeyAction, String>,
+}
+
+fn default_context_window() -> usize {
+    10
 }
 
 impl Settings {
@@ -34,8 +129,14 @@ impl Settings {
             avatar: PathBuf::new(),
             keep_context: true,
             model: String::new(),
-            ollama_address: "localhost:11434".to_string(),
-            keep_alive_model: "5m".into(),
+            ollama_address: String::new(),
+            keep_alive_model: String::new(),
+            servers: vec![ServerProfile::local()],
+            active_server: 0,
+            context_strategy: context::Strategy::default(),
+            context_window: default_context_window(),
+            presets: HashMap::new(),
+            key_bindings: HashMap::new(),
         }
     }
 
@@ -58,22 +159,162 @@ impl Settings {
         self
     }
 
+    pub fn server(&self) -> &ServerProfile {
+        &self.servers[self.active_server]
+    }
+
+    fn server_mut(&mut self) -> &mut ServerProfile {
+        &mut self.servers[self.active_server]
+    }
+
+    pub fn server_names(&self) -> Vec<String> {
+        self.servers
+            .iter()
+            .map(|server| server.name.clone())
+            .collect()
+    }
+
+    pub fn set_active_server(&mut self, index: usize) -> &mut Self {
+        if index < self.servers.len() {
+            self.active_server = index;
+        }
+        self
+    }
+
+    /// Adds a server and makes it the active one.
+    pub fn add_server(&mut self, profile: ServerProfile) -> &mut Self {
+        self.servers.push(profile);
+        self.active_server = self.servers.len() - 1;
+        self
+    }
+
+    /// Removes the active server, unless it is the last one left.
+    pub fn remove_server(&mut self) -> &mut Self {
+        if self.servers.len() > 1 {
+            self.servers.remove(self.active_server);
+            self.active_server = self.active_server.min(self.servers.len() - 1);
+        }
+        self
+    }
+
+    pub fn set_server_name(&mut self, name: String) -> &mut Self {
+        self.server_mut().name = name;
+        self
+    }
+
+    pub fn set_backend(&mut self, backend: api::Backend) -> &mut Self {
+        self.server_mut().backend = backend;
+        self
+    }
+
+    pub fn toggle_favorite(&mut self, model: String) -> &mut Self {
+        let favorites = &mut self.server_mut().favorites;
+        if let Some(index) = favorites.iter().position(|favorite| *favorite == model) {
+            favorites.remove(index);
+        } else {
+            favorites.push(model);
+        }
+        self
+    }
+
+    pub fn server_ids(&self) -> Vec<String> {
+        self.servers
+            .iter()
+            .map(|server| server.id.clone())
+            .collect()
+    }
+
+    pub fn set_ca_certificate(&mut self, path: PathBuf) -> &mut Self {
+        self.server_mut().ca_certificate = path;
+        self
+    }
+
     pub fn set_model(&mut self, model: String) -> &mut Self {
-        self.model = model;
+        self.server_mut().model = model;
         self
     }
 
     pub fn set_ollama_address(&mut self, ip_port: String) -> &mut Self {
-        self.ollama_address = ip_port;
+        self.server_mut().address = ip_port;
         self
     }
 
     pub fn set_keep_alive_model(&mut self, time: String) -> &mut Self {
-        self.keep_alive_model = time;
+        self.server_mut().keep_alive = time;
+        self
+    }
+
+    /// Fills in what older settings files lack. Only derives from what is
+    /// in the file, so that it needs no writing back.
+    fn migrate(&mut self) {
+        if self.servers.is_empty() {
+            let mut profile = ServerProfile::local();
+            if !self.ollama_address.is_empty() {
+                profile.address = std::mem::take(&mut self.ollama_address);
+            }
+            if !self.keep_alive_model.is_empty() {
+                profile.keep_alive = std::mem::take(&mut self.keep_alive_model);
+            }
+            profile.model = std::mem::take(&mut self.model);
+
+            self.servers.push(profile);
+        }
+
+        // Profile ids must stay the same from one load to the next.
+        for (index, server) in self.servers.iter_mut().enumerate() {
+            if server.id.is_empty() {
+                server.id = format!("server-{}", index);
+            }
+        }
+
+        self.active_server = self.active_server.min(self.servers.len() - 1);
+    }
+
+    pub fn preset(&self, model: &str) -> ModelPreset {
+        self.presets.get(model).cloned().unwrap_or_default()
+    }
+
+    /// An empty preset is dropped rather than stored.
+    pub fn set_preset(&mut self, model: String, preset: ModelPreset) -> &mut Self {
+        if preset == ModelPreset::default() {
+            self.presets.remove(&model);
+        } else {
+            self.presets.insert(model, preset);
+        }
+        self
+    }
+
+    pub fn key_binding(&self, action: KeyAction) -> String {
+        self.key_bindings
+            .get(&action)
+            .cloned()
+            .unwrap_or_else(|| action.default_binding().to_string())
+    }
+
+    /// Only bindings that differ from the default are stored.
+    pub fn set_key_binding(&mut self, action: KeyAction, binding: String) -> &mut Self {
+        if binding == action.default_binding() {
+            self.key_bindings.remove(&action);
+        } else {
+            self.key_bindings.insert(action, binding);
+        }
+        self
+    }
+
+    pub fn set_context_strategy(&mut self, strategy: context::Strategy) -> &mut Self {
+        self.context_strategy = strategy;
+        self
+    }
+
+    pub fn set_context_window(&mut self, turns: usize) -> &mut Self {
+        self.context_window = turns;
         self
     }
 
-    pub fn load() -> Settings {
+    /// The saved settings, or the defaults when there are none. A file that
+    /// cannot be parsed is moved aside, so that saving the defaults does not
+    /// overwrite it, and the reason is returned along with them.
+    pub fn load() -> (Settings, Option<String>) {
         let data_path = dirs::config_dir()
             .expect("xdg-config not found")
             .join("cosmic-ext-applet-ollama")
@@ -81,12 +322,26 @@ impl Settings {
 
         let settings: Settings = Settings::new();
 
-        if let Ok(opened) = File::open(data_path) {
+        if let Ok(opened) = File::open(&data_path) {
             let reader = BufReader::new(opened);
-            let settings: Settings = from_reader(reader).expect("Cannot parse settings file");
-            return settings;
+            match from_reader::<_, Settings>(reader) {
+                Ok(mut loaded) => {
+                    loaded.migrate();
+                    return (loaded, None);
+                }
+                Err(why) => {
+                    let backup = data_path.with_extension(format!(
+                        "ron.{}.bak",
+                        chrono::Local::now().format("%Y%m%d%H%M%S")
+                    ));
+                    fs::rename(&data_path, &backup)
+                        .expect("Cannot parse settings file nor move it aside");
+                    let reason = format!("{} ({})", why, backup.display());
+                    return (settings, Some(reason));
+                }
+            }
         }
-        settings
+        (settings, None)
     }
 
     pub fn save(&self) -> anyhow::Result<()> {
@@ -97,10 +352,14 @@ impl Settings {
         fs::create_dir_all(&data_path)?;
 
         let pretty = PrettyConfig::default();
-        let ron_string = to_string_pretty(self, pretty).unwrap();
+        let ron_string = to_string_pretty(self, pretty)?;
 
-        let mut file = File::create(data_path.join("settings.ron"))?;
+        // Written aside and renamed, so that a crash never leaves half a file.
+        let temporary = data_path.join("settings.ron.tmp");
+        let mut file = File::create(&temporary)?;
         file.write_all(ron_string.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(temporary, data_path.join("settings.ron"))?;
 
         Ok(())
     }
@@ -1,14 +1,161 @@
-use crate::api::ListModels;
+use ron::ser::to_string;
+use std::{fs, path::PathBuf};
 
-pub fn installed_models() -> Vec<String> {
-    let mut models: Vec<String> = Vec::new();
+use crate::{
+    api::{Connection, ListModels, Model, Tags},
+    fl,
+};
 
-    let tags = ListModels::new();
-    if let Ok(response) = tags.result {
-        response.models.iter().for_each(|field| {
-            models.push(field.model.to_owned());
-        });
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    Vision,
+    Tools,
+    Thinking,
+    Embedding,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 4] = [
+        Capability::Vision,
+        Capability::Tools,
+        Capability::Thinking,
+        Capability::Embedding,
+    ];
+
+    fn key(&self) -> &'static str {
+        match self {
+            Capability::Vision => "vision",
+            Capability::Tools => "tools",
+            Capability::Thinking => "thinking",
+            Capability::Embedding => "embedding",
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Capability::Vision => fl!("capability-vision"),
+            Capability::Tools => fl!("capability-tools"),
+            Capability::Thinking => fl!("capability-thinking"),
+            Capability::Embedding => fl!("capability-embedding"),
+        }
+    }
+
+    pub fn icon(&self) -> &'static str {
+        match self {
+            Capability::Vision => "camera-photo-symbolic",
+            Capability::Tools => "applications-engineering-symbolic",
+            Capability::Thinking => "dialog-question-symbolic",
+            Capability::Embedding => "view-grid-symbolic",
+        }
+    }
+}
+
+/// Older servers do not report capabilities; a CLIP projector still gives
+/// vision models away.
+pub fn capabilities(model: &Model) -> Vec<Capability> {
+    let mut capabilities: Vec<Capability> = Capability::ALL
+        .into_iter()
+        .filter(|capability| model.capabilities.iter().any(|key| key == capability.key()))
+        .collect();
+
+    let families = model
+        .details
+        .as_ref()
+        .and_then(|details| details.families.as_ref());
+    if model.capabilities.is_empty()
+        && families.is_some_and(|families| {
+            families
+                .iter()
+                .any(|family| family == "clip" || family == "mllama")
+        })
+    {
+        capabilities.push(Capability::Vision);
+    }
+
+    capabilities
+}
+
+pub fn family(model: &Model) -> Option<&str> {
+    model
+        .details
+        .as_ref()
+        .and_then(|details| details.family.as_deref())
+        .filter(|family| !family.is_empty())
+}
+
+/// Parameter size, quantization, disk size and modification date, as far as
+/// they are known.
+pub fn description(model: &Model) -> String {
+    let details = model.details.as_ref();
+
+    [
+        details.and_then(|details| details.parameter_size.clone()),
+        details.and_then(|details| details.quantization_level.clone()),
+        model.size.map(disk_size),
+        model
+            .modified_at
+            .as_ref()
+            .and_then(|date| date.get(..10))
+            .map(str::to_string),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join(" · ")
+}
+
+fn disk_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Case-insensitive match on the name or the family.
+pub fn matches(model: &Model, search: &str) -> bool {
+    let search = search.trim().to_lowercase();
+
+    search.is_empty()
+        || model.model.to_lowercase().contains(&search)
+        || family(model).is_some_and(|family| family.to_lowercase().contains(&search))
+}
+
+fn cache_path(server: &str) -> PathBuf {
+    dirs::cache_dir()
+        .expect("xdg-cache not found")
+        .join("cosmic-ext-applet-ollama")
+        .join(format!("models-{}.ron", server))
+}
+
+pub fn names(tags: &Tags) -> Vec<String> {
+    tags.models
+        .iter()
+        .map(|model| model.model.clone())
+        .collect()
+}
+
+/// What the server listed last time, so the model list is filled before it
+/// answers.
+pub fn cached_models(server: &str) -> Option<Tags> {
+    let contents = fs::read_to_string(cache_path(server)).ok()?;
+    ron::from_str(&contents).ok()
+}
+
+/// Asks the server for its models and caches the answer.
+pub async fn installed_models(connection: Connection, server: String) -> anyhow::Result<Tags> {
+    let tags = ListModels::new(connection).await?.tags;
+
+    let path = cache_path(&server);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
     }
+    fs::write(path, to_string(&tags)?)?;
 
-    models
+    Ok(tags)
 }
@@ -0,0 +1,332 @@
+use futures::{future::BoxFuture, FutureExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    api::{
+        lines, BotResponse, ChatMessage, Connection, Model, Options, Parameters, Provider,
+        Responses, Role, Tags,
+    },
+    chat::Image,
+};
+
+/// Servers speaking the OpenAI chat completions protocol, such as llama.cpp
+/// server, vLLM or LM Studio. Replies are translated into Ollama's shape;
+/// timings are not reported, only token counts where the server sends them.
+pub struct OpenAi {
+    pub connection: Connection,
+}
+
+impl OpenAi {
+    /// The profile may point at the server root or at its `/v1`.
+    fn url(connection: &Connection, path: &str) -> String {
+        format!("{}/v1{}", connection.base_url.trim_end_matches("/v1"), path)
+    }
+}
+
+/// Keep-alive, thinking and the context size are Ollama's own and have no
+/// counterpart here; the server's settings apply instead.
+#[derive(Serialize)]
+struct ChatQuery {
+    model: String,
+    messages: Vec<Value>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    /// Not in the OpenAI API, but llama.cpp and vLLM accept it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+}
+
+impl ChatQuery {
+    fn new(model: String, messages: &[ChatMessage], options: &Options, stream: bool) -> Self {
+        Self {
+            model,
+            messages: messages.iter().map(message).collect(),
+            stream,
+            stream_options: stream.then(|| json!({ "include_usage": true })),
+            temperature: options.temperature,
+            top_p: options.top_p,
+            top_k: options.top_k,
+            seed: options.seed,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Completion {
+    #[serde(default)]
+    model: String,
+    #[serde(default)]
+    choices: Vec<Choice>,
+    usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    /// Set while streaming.
+    delta: Option<Content>,
+    /// Set on complete replies.
+    message: Option<Content>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Content {
+    content: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+struct Usage {
+    prompt_tokens: Option<u64>,
+    completion_tokens: Option<u64>,
+}
+
+impl Completion {
+    fn text(&self) -> String {
+        self.choices
+            .iter()
+            .filter_map(|choice| choice.delta.as_ref().or(choice.message.as_ref()))
+            .filter_map(|content| content.content.as_deref())
+            .collect()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelList {
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelEntry {
+    id: String,
+    /// vLLM.
+    max_model_len: Option<u64>,
+    /// LM Studio.
+    max_context_length: Option<u64>,
+    /// llama.cpp server.
+    meta: Option<ModelMeta>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelMeta {
+    n_ctx_train: Option<u64>,
+}
+
+/// Images go inline as data URLs, next to the text.
+fn message(message: &ChatMessage) -> Value {
+    let role = match message.role {
+        Role::System => "system",
+        Role::User => "user",
+        Role::Assistant => "assistant",
+    };
+
+    if message.images.is_empty() {
+        return json!({ "role": role, "content": message.content });
+    }
+
+    let mut parts = vec![json!({ "type": "text", "text": message.content })];
+    parts.extend(
+        message
+            .images
+            .iter()
+            .filter_map(|image| Image::from_base64(image.clone()).ok())
+            .map(|image| json!({ "type": "image_url", "image_url": { "url": image.data_url() } })),
+    );
+
+    json!({ "role": role, "content": parts })
+}
+
+fn response(model: &str, content: String, done: bool, usage: Option<Usage>) -> BotResponse {
+    BotResponse {
+        model: model.to_string(),
+        created_at: String::new(),
+        message: Some(ChatMessage {
+            role: Role::Assistant,
+            content,
+            images: Vec::new(),
+        }),
+        done,
+        total_duration: None,
+        load_duration: None,
+        prompt_eval_count: usage.and_then(|usage| usage.prompt_tokens),
+        prompt_eval_duration: None,
+        eval_count: usage.and_then(|usage| usage.completion_tokens),
+        eval_duration: None,
+    }
+}
+
+impl Provider for OpenAi {
+    fn chat(
+        &self,
+        model: String,
+        messages: Vec<ChatMessage>,
+        parameters: Parameters,
+    ) -> BoxFuture<'static, anyhow::Result<Responses>> {
+        let connection = self.connection.clone();
+
+        async move {
+            let body = connection
+                .client()
+                .post(Self::url(&connection, "/chat/completions"))
+                .json(&ChatQuery::new(
+                    model.clone(),
+                    &messages,
+                    &parameters.options,
+                    true,
+                ))
+                .send()
+                .await?
+                .error_for_status()?
+                .bytes_stream();
+
+            // Server-sent events: one `data:` line per chunk, then `[DONE]`.
+            // Usage comes in a last chunk without choices, if at all.
+            let responses = async_stream::try_stream! {
+                let mut lines = Box::pin(lines(body));
+                let mut usage = None;
+
+                while let Some(line) = lines.next().await {
+                    let line = line?;
+                    let Some(data) = line.strip_prefix("data:") else {
+                        continue;
+                    };
+                    let data = data.trim();
+                    if data == "[DONE]" {
+                        break;
+                    }
+
+                    let chunk: Completion = serde_json::from_str(data)?;
+                    usage = chunk.usage.or(usage);
+
+                    let text = chunk.text();
+                    if !text.is_empty() {
+                        yield response(&model, text, false, None);
+                    }
+                }
+
+                yield response(&model, String::new(), true, usage);
+            };
+
+            Ok(responses.boxed())
+        }
+        .boxed()
+    }
+
+    fn complete(
+        &self,
+        model: String,
+        messages: Vec<ChatMessage>,
+        parameters: Parameters,
+    ) -> BoxFuture<'static, anyhow::Result<BotResponse>> {
+        let connection = self.connection.clone();
+
+        async move {
+            let completion = connection
+                .client()
+                .post(Self::url(&connection, "/chat/completions"))
+                .json(&ChatQuery::new(
+                    model.clone(),
+                    &messages,
+                    &parameters.options,
+                    false,
+                ))
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<Completion>()
+                .await?;
+
+            let model = if completion.model.is_empty() {
+                model
+            } else {
+                completion.model.clone()
+            };
+
+            Ok(response(&model, completion.text(), true, completion.usage))
+        }
+        .boxed()
+    }
+
+    fn context_length(&self, model: String) -> BoxFuture<'static, anyhow::Result<Option<u64>>> {
+        let connection = self.connection.clone();
+
+        async move {
+            let models = connection
+                .client()
+                .get(Self::url(&connection, "/models"))
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<ModelList>()
+                .await?;
+
+            Ok(models
+                .data
+                .into_iter()
+                .find(|entry| entry.id == model)
+                .and_then(|entry| {
+                    entry
+                        .max_model_len
+                        .or(entry.max_context_length)
+                        .or(entry.meta.and_then(|meta| meta.n_ctx_train))
+                }))
+        }
+        .boxed()
+    }
+
+    fn list_models(&self) -> BoxFuture<'static, anyhow::Result<Tags>> {
+        let connection = self.connection.clone();
+
+        async move {
+            let models = connection
+                .client()
+                .get(Self::url(&connection, "/models"))
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<ModelList>()
+                .await?;
+
+            Ok(Tags {
+                models: models
+                    .data
+                    .into_iter()
+                    .map(|entry| Model {
+                        name: entry.id.clone(),
+                        model: entry.id,
+                        modified_at: None,
+                        size: None,
+                        digest: None,
+                        details: None,
+                        capabilities: Vec::new(),
+                    })
+                    .collect(),
+            })
+        }
+        .boxed()
+    }
+
+    /// There is no version endpoint in the protocol, so listing the models
+    /// stands in for it.
+    fn version(&self) -> BoxFuture<'static, anyhow::Result<Option<String>>> {
+        let connection = self.connection.clone();
+
+        async move {
+            connection
+                .client()
+                .get(Self::url(&connection, "/models"))
+                .send()
+                .await?
+                .error_for_status()?;
+
+            Ok(None)
+        }
+        .boxed()
+    }
+}
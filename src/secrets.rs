@@ -0,0 +1,97 @@
+use once_cell::sync::Lazy;
+use oo7::Keyring;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::RwLock};
+
+const APPLICATION: &str = "cosmic-ext-applet-ollama";
+
+/// Authentication sent to a server profile, kept in the Secret Service.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Credentials {
+    pub token: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Credentials {
+    pub fn is_empty(&self) -> bool {
+        self.token.is_empty() && self.headers.is_empty()
+    }
+}
+
+/// The keyring is only reachable asynchronously, while connections are set
+/// up synchronously, so loaded credentials are kept here.
+static CACHE: Lazy<RwLock<HashMap<String, Credentials>>> = Lazy::new(Default::default);
+
+fn attributes(server: &str) -> HashMap<&str, &str> {
+    HashMap::from([("application", APPLICATION), ("server", server)])
+}
+
+/// Cached credentials of a server profile; empty until `load` has run.
+pub fn get(server: &str) -> Credentials {
+    CACHE
+        .read()
+        .ok()
+        .and_then(|cache| cache.get(server).cloned())
+        .unwrap_or_default()
+}
+
+/// Reads the credentials of the given server profiles from the keyring.
+pub async fn load(servers: Vec<String>) -> anyhow::Result<()> {
+    let keyring = Keyring::new().await?;
+    keyring.unlock().await?;
+
+    for server in servers {
+        let items = keyring.search_items(&attributes(&server)).await?;
+        let Some(item) = items.first() else {
+            continue;
+        };
+
+        let secret = item.secret().await?;
+        let credentials: Credentials = serde_json::from_slice(&secret)?;
+
+        if let Ok(mut cache) = CACHE.write() {
+            cache.insert(server, credentials);
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the credentials of a server profile, removing the keyring entry
+/// when they are empty.
+pub async fn store(server: String, credentials: Credentials) -> anyhow::Result<()> {
+    let keyring = Keyring::new().await?;
+    keyring.unlock().await?;
+
+    if credentials.is_empty() {
+        keyring.delete(&attributes(&server)).await?;
+    } else {
+        keyring
+            .create_item(
+                &format!("Ollama server credentials ({})", server),
+                &attributes(&server),
+                serde_json::to_vec(&credentials)?,
+                true,
+            )
+            .await?;
+    }
+
+    if let Ok(mut cache) = CACHE.write() {
+        cache.insert(server, credentials);
+    }
+
+    Ok(())
+}
+
+/// Deletes the credentials of a removed server profile.
+pub async fn remove(server: String) -> anyhow::Result<()> {
+    let keyring = Keyring::new().await?;
+    keyring.unlock().await?;
+    keyring.delete(&attributes(&server)).await?;
+
+    if let Ok(mut cache) = CACHE.write() {
+        cache.remove(&server);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,89 @@
+use ashpd::desktop::{
+    global_shortcuts::{GlobalShortcuts, NewShortcut},
+    Session,
+};
+use cosmic::{
+    iced::futures::{Stream, StreamExt},
+    iced_futures::MaybeSend,
+};
+
+/// System-wide shortcuts, registered through the GlobalShortcuts portal.
+/// The desktop decides the final key combination and lets the user
+/// change it; the triggers here are only suggestions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shortcut {
+    /// Opens or closes the popup, focusing the prompt.
+    Toggle,
+    /// Opens the popup with the primary selection quoted in the prompt.
+    AskAboutSelection,
+}
+
+impl Shortcut {
+    const ALL: [Shortcut; 2] = [Shortcut::Toggle, Shortcut::AskAboutSelection];
+
+    fn id(&self) -> &'static str {
+        match self {
+            Shortcut::Toggle => "toggle",
+            Shortcut::AskAboutSelection => "ask-about-selection",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Shortcut::Toggle => "Open the Ollama applet",
+            Shortcut::AskAboutSelection => "Ask Ollama about the selected text",
+        }
+    }
+
+    fn trigger(&self) -> &'static str {
+        match self {
+            Shortcut::Toggle => "LOGO+ALT+o",
+            Shortcut::AskAboutSelection => "LOGO+ALT+s",
+        }
+    }
+}
+
+async fn register() -> anyhow::Result<(
+    GlobalShortcuts<'static>,
+    Session<'static, GlobalShortcuts<'static>>,
+)> {
+    let portal = GlobalShortcuts::new().await?;
+    let session = portal.create_session().await?;
+
+    let shortcuts: Vec<NewShortcut> = Shortcut::ALL
+        .iter()
+        .map(|shortcut| {
+            NewShortcut::new(shortcut.id(), shortcut.description())
+                .preferred_trigger(Some(shortcut.trigger()))
+        })
+        .collect();
+    portal
+        .bind_shortcuts(&session, &shortcuts, None)
+        .await?
+        .response()?;
+
+    Ok((portal, session))
+}
+
+/// Activated shortcuts; ends right away where the portal is not available.
+pub fn activations() -> impl Stream<Item = Shortcut> + MaybeSend {
+    async_stream::stream! {
+        // The session has to stay open, or the shortcuts go away.
+        let Ok((portal, _session)) = register().await else {
+            return;
+        };
+        let Ok(activated) = portal.receive_activated().await else {
+            return;
+        };
+        let mut activated = std::pin::pin!(activated);
+
+        while let Some(activation) = activated.next().await {
+            if let Some(shortcut) = Shortcut::ALL
+                .into_iter()
+                .find(|shortcut| shortcut.id() == activation.shortcut_id())
+            {
+                yield shortcut;
+            }
+        }
+    }
+}
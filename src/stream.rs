@@ -2,62 +2,78 @@ use cosmic::{
     iced::futures::{Stream, StreamExt},
     iced_futures::MaybeSend,
 };
+use std::{collections::HashMap, future::Future};
 use tokio::sync::{mpsc, oneshot};
 
-use crate::api::{Bot, BotResponse, PullModel, PullModelResponse, RemoveModel};
+use crate::api::{
+    Bot, BotResponse, ChatMessage, Connection, Parameters, PullModel, PullModelResponse,
+    RemoveModel,
+};
+
+/// Chosen by the sender; every event of a request carries it.
+pub type RequestId = u64;
 
 #[derive(Debug, Clone)]
 pub enum Event {
     Ready(mpsc::Sender<Request>),
-    Response(BotResponse),
-    PullResponse(PullModelResponse),
-    PullDone,
-    RemoveStatus(String),
-    RemovedModel,
-    Done,
+    Response(RequestId, BotResponse),
+    Failed(RequestId, String),
+    Done(RequestId),
+    PullResponse(RequestId, PullModelResponse),
+    PullDone(RequestId),
+    RemoveStatus(RequestId, String),
+    RemovedModel(RequestId),
 }
 
 #[derive(Debug, Clone)]
 pub enum Request {
-    Ask((String, String, Vec<String>, String)),
-    AskWithContext((String, String, Vec<String>, Option<Vec<u64>>, String)),
-    PullModel(String),
-    RemoveModel(String),
+    Chat(RequestId, Connection, String, Vec<ChatMessage>, Parameters),
+    PullModel(RequestId, Connection, String),
+    RemoveModel(RequestId, Connection, String),
+    /// Stops a running request; it sends no further events.
+    Cancel(RequestId),
 }
 
+/// Runs every request in its own task, so chats, pulls and removals go on
+/// concurrently. The service ends once the sender from `Event::Ready` is
+/// dropped.
 pub fn service() -> impl Stream<Item = Event> + MaybeSend {
-    let (requests_tx, mut requests_rx) = mpsc::channel(4);
-    let (responses_tx, mut responses_rx) = mpsc::channel(4);
+    let (requests_tx, mut requests_rx) = mpsc::channel(16);
+    let (responses_tx, mut responses_rx) = mpsc::channel(16);
 
     let service_future = async move {
-        let _res = responses_tx.send(Event::Ready(requests_tx.clone())).await;
+        let _res = responses_tx.send(Event::Ready(requests_tx)).await;
 
-        let client = &mut None;
-        let pull_client = &mut None;
+        let mut running: HashMap<RequestId, oneshot::Sender<()>> = HashMap::new();
 
         while let Some(request) = requests_rx.recv().await {
+            // Finished tasks have dropped their end of the kill switch.
+            running.retain(|_, kill_tx| !kill_tx.is_closed());
+
             match request {
-                Request::Ask((model, text, images, keep_alive)) => {
-                    _ = client_request(model, text, images, None, keep_alive, &responses_tx, client)
-                        .await
-                }
-                Request::AskWithContext((model, text, images, context, keep_alive)) => {
-                    _ = client_request(
+                Request::Chat(id, connection, model, messages, parameters) => {
+                    let task = chat_request(
+                        id,
+                        connection,
                         model,
-                        text,
-                        images,
-                        context,
-                        keep_alive,
-                        &responses_tx,
-                        client,
-                    )
-                    .await
+                        messages,
+                        parameters,
+                        responses_tx.clone(),
+                    );
+                    running.insert(id, spawn(task));
+                }
+                Request::PullModel(id, connection, model) => {
+                    let task = pull_request(id, connection, model, responses_tx.clone());
+                    running.insert(id, spawn(task));
                 }
-                Request::PullModel(model) => {
-                    _ = pull_request(model.to_string(), &responses_tx, pull_client).await
+                Request::RemoveModel(id, connection, model) => {
+                    let task = remove_request(id, connection, model, responses_tx.clone());
+                    running.insert(id, spawn(task));
                 }
-                Request::RemoveModel(model) => {
-                    _ = remove_request(model.to_string(), &responses_tx).await
+                Request::Cancel(id) => {
+                    if let Some(kill_tx) = running.remove(&id) {
+                        let _res = kill_tx.send(());
+                    }
                 }
             }
         }
@@ -72,105 +88,90 @@ pub fn service() -> impl Stream<Item = Event> + MaybeSend {
     }
 }
 
-async fn client_request<'a>(
-    model: String,
-    prompt: String,
-    images: Vec<String>,
-    context: Option<Vec<u64>>,
-    keep_alive_model: String,
-    tx: &mpsc::Sender<Event>,
-    client: &'a mut Option<(Bot, oneshot::Sender<()>)>,
-) -> &'a mut Option<(Bot, oneshot::Sender<()>)> {
-    if client.is_none() {
-        *client = match Bot::new(model.to_string(), prompt, images, context, keep_alive_model).await
-        {
-            Ok((new_client, responses)) => {
-                let tx = tx.clone();
-
-                let (kill_tx, kill_rx) = oneshot::channel();
-                let listener = async {
-                    let listener = Box::pin(async move {
-                        let mut responses = std::pin::pin!(responses);
-                        while let Some(Ok(response)) = responses.next().await {
-                            let data = serde_json::from_slice::<BotResponse>(&response);
-
-                            if let Ok(res) = data {
-                                let _res = tx.send(Event::Response(res)).await;
-                            }
-                        }
-                        let _ = tx.send(Event::Done).await;
-                    });
-
-                    let killswitch = Box::pin(async move {
-                        let _res = kill_rx.await;
-                    });
+/// Spawns a request, returning the switch that cancels it.
+fn spawn(task: impl Future<Output = ()> + Send + 'static) -> oneshot::Sender<()> {
+    let (kill_tx, kill_rx) = oneshot::channel();
 
-                    futures::future::select(listener, killswitch).await;
-                };
+    let _res = tokio::task::spawn(async move {
+        futures::future::select(Box::pin(task), kill_rx).await;
+    });
 
-                let _res = tokio::task::spawn(listener);
+    kill_tx
+}
 
-                Some((new_client, kill_tx))
+async fn chat_request(
+    id: RequestId,
+    connection: Connection,
+    model: String,
+    messages: Vec<ChatMessage>,
+    parameters: Parameters,
+    tx: mpsc::Sender<Event>,
+) {
+    match Bot::new(connection, model, messages, parameters).await {
+        Ok((_bot, responses)) => {
+            let mut responses = std::pin::pin!(responses);
+            while let Some(response) = responses.next().await {
+                match response {
+                    Ok(response) => {
+                        let _res = tx.send(Event::Response(id, response)).await;
+                    }
+                    Err(why) => {
+                        let _res = tx.send(Event::Failed(id, why.to_string())).await;
+                        break;
+                    }
+                }
             }
-            Err(_why) => None,
         }
-    };
+        Err(why) => {
+            let _res = tx.send(Event::Failed(id, why.to_string())).await;
+        }
+    }
 
-    client
+    let _ = tx.send(Event::Done(id)).await;
 }
 
-async fn pull_request<'a>(
+async fn pull_request(
+    id: RequestId,
+    connection: Connection,
     model: String,
-    tx: &mpsc::Sender<Event>,
-    client: &'a mut Option<(PullModel, oneshot::Sender<()>)>,
-) -> &'a mut Option<(PullModel, oneshot::Sender<()>)> {
-    if client.is_none() {
-        *client = match PullModel::new(model).await {
-            Ok((new_client, responses)) => {
-                let tx = tx.clone();
-
-                let (kill_tx, kill_rx) = oneshot::channel();
-                let listener = async {
-                    let listener = Box::pin(async move {
-                        let mut responses = std::pin::pin!(responses);
-                        while let Some(Ok(response)) = responses.next().await {
-                            let data = serde_json::from_slice::<PullModelResponse>(&response);
-
-                            if let Ok(res) = data {
-                                let _res = tx.send(Event::PullResponse(res)).await;
-                            }
+    tx: mpsc::Sender<Event>,
+) {
+    match PullModel::new(connection, model).await {
+        Ok((_client, responses)) => {
+            let mut responses = std::pin::pin!(responses);
+            while let Some(line) = responses.next().await {
+                match line {
+                    Ok(line) => {
+                        if let Ok(res) = serde_json::from_str::<PullModelResponse>(&line) {
+                            let _res = tx.send(Event::PullResponse(id, res)).await;
                         }
-                        let _ = tx.send(Event::PullDone).await;
-                    });
-
-                    let killswitch = Box::pin(async move {
-                        let _res = kill_rx.await;
-                    });
-
-                    futures::future::select(listener, killswitch).await;
-                };
-
-                let _res = tokio::task::spawn(listener);
-
-                Some((new_client, kill_tx))
+                    }
+                    Err(why) => {
+                        let _res = tx.send(Event::Failed(id, why.to_string())).await;
+                        break;
+                    }
+                }
             }
-            Err(_why) => None,
         }
-    };
+        Err(why) => {
+            let _res = tx.send(Event::Failed(id, why.to_string())).await;
+        }
+    }
 
-    client
+    let _ = tx.send(Event::PullDone(id)).await;
 }
 
-async fn remove_request(model: String, tx: &mpsc::Sender<Event>) -> anyhow::Result<()> {
-    if let Ok((_new_client, status_code)) = RemoveModel::new(model).await {
-        if status_code.is_success() {
-            _ = tx.send(Event::RemoveStatus(String::from("Removed successfully")));
-        } else {
-            _ = tx.send(Event::RemoveStatus(String::from("Can't remove model")));
-        };
+async fn remove_request(
+    id: RequestId,
+    connection: Connection,
+    model: String,
+    tx: mpsc::Sender<Event>,
+) {
+    let status = match RemoveModel::new(connection, model).await {
+        Ok((_client, status_code)) if status_code.is_success() => "Removed successfully",
+        _ => "Can't remove model",
     };
 
-    let _ = tx.send(Event::RemovedModel).await;
-
-    Ok(())
+    let _ = tx.send(Event::RemoveStatus(id, String::from(status))).await;
+    let _ = tx.send(Event::RemovedModel(id)).await;
 }
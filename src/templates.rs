@@ -0,0 +1,154 @@
+use ron::ser::{to_string_pretty, PrettyConfig};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::PathBuf};
+
+/// Filled in from the clipboard when the template is used.
+pub const CLIPBOARD: &str = "clipboard";
+/// Filled in from the primary selection when the template is used.
+pub const SELECTION: &str = "selection";
+
+/// A reusable prompt, used by typing `/name`. `{{name}}` placeholders are
+/// asked for before sending.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Template {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub text: String,
+}
+
+impl Template {
+    fn new(name: &str, description: &str, text: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    /// Placeholders in order of first appearance, without duplicates.
+    pub fn placeholders(&self) -> Vec<String> {
+        let mut placeholders: Vec<String> = Vec::new();
+        let mut rest = self.text.as_str();
+
+        while let Some(start) = rest.find("{{") {
+            rest = &rest[start + 2..];
+            let Some(end) = rest.find("}}") else {
+                break;
+            };
+
+            let name = rest[..end].trim().to_string();
+            if !name.is_empty() && !placeholders.contains(&name) {
+                placeholders.push(name);
+            }
+            rest = &rest[end + 2..];
+        }
+
+        placeholders
+    }
+
+    /// Placeholders the user has to fill in.
+    pub fn variables(&self) -> Vec<String> {
+        self.placeholders()
+            .into_iter()
+            .filter(|name| name != CLIPBOARD && name != SELECTION)
+            .collect()
+    }
+
+    pub fn uses(&self, placeholder: &str) -> bool {
+        self.placeholders().iter().any(|name| name == placeholder)
+    }
+
+    /// The prompt with every placeholder replaced; unknown ones become empty.
+    pub fn render(&self, values: &HashMap<String, String>) -> String {
+        let mut prompt = String::new();
+        let mut rest = self.text.as_str();
+
+        while let Some(start) = rest.find("{{") {
+            prompt.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let Some(end) = after.find("}}") else {
+                rest = &rest[start..];
+                break;
+            };
+
+            let name = after[..end].trim();
+            prompt.push_str(values.get(name).map(String::as_str).unwrap_or_default());
+            rest = &after[end + 2..];
+        }
+        prompt.push_str(rest);
+
+        prompt
+    }
+}
+
+fn path() -> PathBuf {
+    dirs::config_dir()
+        .expect("xdg-config not found")
+        .join("cosmic-ext-applet-ollama")
+        .join("templates.ron")
+}
+
+fn defaults() -> Vec<Template> {
+    vec![
+        Template::new(
+            "review",
+            "Review a diff",
+            "Review this diff. Point out bugs, unclear code and missing tests.\n\n{{clipboard}}",
+        ),
+        Template::new(
+            "translate",
+            "Translate the selected text",
+            "Translate the following text to {{language}}. Answer with the translation only.\n\n{{selection}}",
+        ),
+        Template::new(
+            "explain",
+            "Explain an error",
+            "Explain this error and how to fix it:\n\n{{clipboard}}",
+        ),
+    ]
+}
+
+/// The template library, written with a few examples on first use so that
+/// there is something to edit. A library that cannot be read is left alone.
+pub fn load() -> anyhow::Result<Vec<Template>> {
+    let path = path();
+    if !path.exists() {
+        let templates = defaults();
+        save(&templates)?;
+        return Ok(templates);
+    }
+
+    Ok(ron::from_str(&fs::read_to_string(path)?)?)
+}
+
+fn save(templates: &[Template]) -> anyhow::Result<()> {
+    let path = path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, to_string_pretty(templates, PrettyConfig::default())?)?;
+
+    Ok(())
+}
+
+/// The folder holding the library, for opening in a file manager.
+pub fn folder() -> PathBuf {
+    path().parent().map(PathBuf::from).unwrap_or_default()
+}
+
+/// Templates whose name starts with what follows the slash.
+pub fn complete<'a>(templates: &'a [Template], prompt: &str) -> Vec<(usize, &'a Template)> {
+    let Some(name) = prompt.strip_prefix('/') else {
+        return Vec::new();
+    };
+    if name.contains(char::is_whitespace) {
+        return Vec::new();
+    }
+
+    templates
+        .iter()
+        .enumerate()
+        .filter(|(_, template)| template.name.starts_with(name))
+        .collect()
+}
@@ -0,0 +1,145 @@
+use chrono::Local;
+use ron::ser::to_string;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+};
+
+use crate::chat::Stats;
+
+/// Loads slower than this had to read the model from disk rather than find
+/// it already in memory.
+const COLD_LOAD_NS: u64 = 500_000_000;
+
+/// One request to the server, as kept in the local usage log.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Record {
+    pub timestamp: String,
+    pub model: String,
+    pub prompt_tokens: Option<u64>,
+    pub response_tokens: Option<u64>,
+    pub total_duration: Option<u64>,
+    pub load_duration: Option<u64>,
+    pub eval_duration: Option<u64>,
+}
+
+impl Record {
+    pub fn new(model: &str, stats: &Stats) -> Self {
+        Self {
+            timestamp: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            model: model.to_string(),
+            prompt_tokens: stats.prompt_eval_count,
+            response_tokens: stats.eval_count,
+            total_duration: stats.total_duration,
+            load_duration: stats.load_duration,
+            eval_duration: stats.eval_duration,
+        }
+    }
+
+    fn day(&self) -> &str {
+        self.timestamp.get(..10).unwrap_or(&self.timestamp)
+    }
+
+    fn tokens(&self) -> u64 {
+        self.prompt_tokens.unwrap_or_default() + self.response_tokens.unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DailyUsage {
+    pub day: String,
+    pub model: String,
+    pub tokens: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ModelUsage {
+    pub model: String,
+    pub requests: usize,
+    pub tokens_per_second: Option<f64>,
+    pub cold_loads: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Aggregates {
+    /// Newest day first.
+    pub daily: Vec<DailyUsage>,
+    pub models: Vec<ModelUsage>,
+}
+
+fn log_path() -> PathBuf {
+    dirs::data_dir()
+        .expect("xdg-data not found")
+        .join("cosmic-ext-applet-ollama")
+        .join("usage.ron")
+}
+
+/// Appends a record to the log, one RON value per line.
+pub fn append(record: &Record) -> anyhow::Result<()> {
+    let path = log_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", to_string(record)?)?;
+
+    Ok(())
+}
+
+/// Every readable record of the log; damaged lines are skipped.
+pub fn load() -> Vec<Record> {
+    fs::read_to_string(log_path())
+        .map(|contents| {
+            contents
+                .lines()
+                .filter_map(|line| ron::from_str(line).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn aggregate(records: &[Record]) -> Aggregates {
+    let mut daily: BTreeMap<(String, String), u64> = BTreeMap::new();
+    // requests, response tokens, eval nanoseconds, cold loads
+    let mut models: BTreeMap<String, (usize, u64, u64, usize)> = BTreeMap::new();
+
+    for record in records {
+        *daily
+            .entry((record.day().to_string(), record.model.clone()))
+            .or_default() += record.tokens();
+
+        let model = models.entry(record.model.clone()).or_default();
+        model.0 += 1;
+        if let (Some(tokens), Some(duration)) = (record.response_tokens, record.eval_duration) {
+            model.1 += tokens;
+            model.2 += duration;
+        }
+        if record.load_duration.unwrap_or_default() > COLD_LOAD_NS {
+            model.3 += 1;
+        }
+    }
+
+    Aggregates {
+        daily: daily
+            .into_iter()
+            .rev()
+            .map(|((day, model), tokens)| DailyUsage { day, model, tokens })
+            .collect(),
+        models: models
+            .into_iter()
+            .map(
+                |(model, (requests, tokens, duration, cold_loads))| ModelUsage {
+                    model,
+                    requests,
+                    tokens_per_second: (duration > 0)
+                        .then(|| tokens as f64 / (duration as f64 / 1e9)),
+                    cold_loads,
+                },
+            )
+            .collect(),
+    }
+}
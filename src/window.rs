@@ -1,11 +1,16 @@
-use ashpd::desktop::file_chooser::{FileFilter, SelectedFiles};
+use ashpd::desktop::{
+    file_chooser::{FileFilter, SelectedFiles},
+    screenshot::Screenshot,
+    ResponseError,
+};
 use cosmic::{
     app::Core,
     applet::padded_control,
     iced::{
         self,
         alignment::Horizontal,
-        id,
+        clipboard::mime::AllowedMimeTypes,
+        id, keyboard,
         platform_specific::shell::wayland::commands::popup::{destroy_popup, get_popup},
         stream::channel,
         theme::Palette,
@@ -14,52 +19,287 @@ use cosmic::{
     },
     iced_widget::{
         scrollable::{snap_to, RelativeOffset},
-        Scrollable,
+        text_editor, Scrollable,
     },
     theme,
     widget::{self, settings},
     Action, Application, Element, Task as Command,
 };
 use futures::{SinkExt as _, StreamExt as _};
-use std::{path::PathBuf, pin::pin};
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap, HashSet},
+    path::PathBuf,
+    pin::pin,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
 
 use crate::{
+    api::{self, BotResponse, ChatMessage, Role, ShowModel},
     chat::{
         load_conversation, read_conversation_files, Conversation, Image, ImageAttachment,
-        MessageContent, Text,
+        MessageContent, Stats, Summary, Text, Turn,
     },
-    fl,
-    models::installed_models,
+    context, export, fl, import,
+    keybindings::{self, KeyAction},
+    models::{cached_models, capabilities, description, family, installed_models, matches, names},
+    secrets,
+    shortcuts::{self, Shortcut},
     stream::{self, service},
-    Settings,
+    templates::{self, Template},
+    usage, ModelPreset, ServerProfile, Settings,
 };
 
 const ID: &str = "dev.heppen.ollama";
 
+/// What the fitted history depends on: conversation revision, kept
+/// context, whether a reply is continued, strategy, window and context
+/// length.
+type HistoryKey = (u64, bool, bool, context::Strategy, usize, Option<u64>);
+
+/// What a connection is built from: backend, address, extra certificate and
+/// credentials.
+type ConnectionKey = (api::Backend, String, PathBuf, secrets::Credentials);
+
+const HEALTH_INTERVAL: Duration = Duration::from_secs(30);
+/// Pastes longer than this, in characters, become a chip instead of
+/// filling the editor.
+const PASTE_CHIP_LENGTH: usize = 1000;
+
 #[derive(Debug, Clone)]
 pub enum Pages {
     Chat,
     Settings,
+    Usage,
+    Models,
+    Preset,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum PresetField {
+    SystemPrompt,
+    Temperature,
+    TopP,
+    TopK,
+    NumCtx,
+    Seed,
+    KeepAlive,
+}
+
+/// One model's reply in a comparison.
+#[derive(Debug, Clone)]
+struct Candidate {
+    id: stream::RequestId,
+    model: String,
+    text: String,
+    final_response: Option<BotResponse>,
+    error: Option<String>,
+    first_token: Option<Duration>,
+    stats: Option<Stats>,
+    done: bool,
+}
+
+impl Candidate {
+    fn new(id: stream::RequestId, model: String) -> Self {
+        Self {
+            id,
+            model,
+            text: String::new(),
+            final_response: None,
+            error: None,
+            first_token: None,
+            stats: None,
+            done: false,
+        }
+    }
+
+    /// Servers that report no timings still get the wall-clock time, so
+    /// that candidates can be compared.
+    fn finish(&mut self, elapsed: Option<Duration>) {
+        let mut stats = self
+            .final_response
+            .as_ref()
+            .map(Stats::from)
+            .unwrap_or_default();
+        stats.time_to_first_token = self.first_token.map(|duration| duration.as_nanos() as u64);
+        if stats.total_duration.is_none() {
+            stats.total_duration = elapsed.map(|duration| duration.as_nanos() as u64);
+        }
+
+        self.stats = Some(stats);
+        self.done = true;
+    }
+}
+
+/// Image data offered on the clipboard, such as a copied screenshot.
+#[derive(Debug, Clone)]
+struct ClipboardImage(Image);
+
+impl AllowedMimeTypes for ClipboardImage {
+    fn allowed() -> Cow<'static, [String]> {
+        Cow::Owned(vec![
+            "image/png".to_string(),
+            "image/jpeg".to_string(),
+            "image/webp".to_string(),
+        ])
+    }
+}
+
+impl TryFrom<(Vec<u8>, String)> for ClipboardImage {
+    type Error = anyhow::Error;
+
+    fn try_from((data, _mime): (Vec<u8>, String)) -> Result<Self, Self::Error> {
+        if data.is_empty() {
+            anyhow::bail!("empty clipboard");
+        }
+
+        Ok(Self(Image::from_bytes(data)))
+    }
+}
+
+/// A template being filled in before it is sent.
+#[derive(Debug, Clone)]
+struct TemplateForm {
+    index: usize,
+    /// Variables in order of appearance, with what was typed for them.
+    values: Vec<(String, String)>,
+    clipboard: String,
+    selection: String,
+    /// Clipboard reads still on their way.
+    reads: usize,
+}
+
+impl TemplateForm {
+    fn values(&self) -> HashMap<String, String> {
+        self.values
+            .iter()
+            .cloned()
+            .chain([
+                (templates::CLIPBOARD.to_string(), self.clipboard.clone()),
+                (templates::SELECTION.to_string(), self.selection.clone()),
+            ])
+            .collect()
+    }
+}
+
+/// A preset while it is edited; numbers stay text until they are saved.
+#[derive(Debug, Clone, Default)]
+struct PresetDraft {
+    system_prompt: String,
+    temperature: String,
+    top_p: String,
+    top_k: String,
+    num_ctx: String,
+    seed: String,
+    keep_alive: String,
+    think: usize,
+}
+
+impl PresetDraft {
+    const THINK: [Option<bool>; 3] = [None, Some(true), Some(false)];
+
+    fn new(preset: &ModelPreset) -> Self {
+        fn text<T: ToString>(value: Option<T>) -> String {
+            value.map(|value| value.to_string()).unwrap_or_default()
+        }
+
+        Self {
+            system_prompt: preset.system_prompt.clone(),
+            temperature: text(preset.options.temperature),
+            top_p: text(preset.options.top_p),
+            top_k: text(preset.options.top_k),
+            num_ctx: text(preset.options.num_ctx),
+            seed: text(preset.options.seed),
+            keep_alive: preset.keep_alive.clone().unwrap_or_default(),
+            think: Self::THINK
+                .iter()
+                .position(|think| *think == preset.think)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Fields that do not parse are left unset.
+    fn preset(&self) -> ModelPreset {
+        ModelPreset {
+            system_prompt: self.system_prompt.trim().to_string(),
+            options: api::Options {
+                temperature: self.temperature.trim().parse().ok(),
+                top_p: self.top_p.trim().parse().ok(),
+                top_k: self.top_k.trim().parse().ok(),
+                num_ctx: self.num_ctx.trim().parse().ok(),
+                seed: self.seed.trim().parse().ok(),
+            },
+            keep_alive: Some(self.keep_alive.trim().to_string())
+                .filter(|keep_alive| !keep_alive.is_empty()),
+            think: Self::THINK[self.think],
+        }
+    }
+
+    fn field(&mut self, field: PresetField) -> &mut String {
+        match field {
+            PresetField::SystemPrompt => &mut self.system_prompt,
+            PresetField::Temperature => &mut self.temperature,
+            PresetField::TopP => &mut self.top_p,
+            PresetField::TopK => &mut self.top_k,
+            PresetField::NumCtx => &mut self.num_ctx,
+            PresetField::Seed => &mut self.seed,
+            PresetField::KeepAlive => &mut self.keep_alive,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ServerStatus {
+    Unknown,
+    Connected(api::Version),
+    Disconnected(String),
 }
 
 #[derive(Debug, Clone)]
 pub enum StreamingRequest {
     Idle,
-    Ask,
-    AskWithContext,
-    PullModel,
-    RemoveModel,
+    Summarize,
+    Chat(stream::RequestId),
+    /// Each candidate of the comparison has its own request.
+    Compare,
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
     ChatPage,
     SettingsPage,
+    UsagePage,
+    ModelsPage,
+    ModelSearch(String),
+    ToggleCompareModel(String),
+    ClearCompare,
+    PickReply(usize),
+    EditPreset(String),
+    PresetInput(PresetField, String),
+    PresetThink(usize),
+    SavePreset,
+    ResetPreset,
+    ToggleFavorite(String),
     PopupClosed(Id),
     TogglePopup,
-    EnterPrompt(String),
+    EditPrompt(text_editor::Action),
+    SubmitPrompt,
+    ExpandPasted(usize),
+    RemovePasted(usize),
     SendPrompt(String),
-    ChangeModel(usize),
+    SendQueued,
+    UseTemplate(usize),
+    TemplateInput(usize, String),
+    TemplateClipboard(Option<String>),
+    TemplateSelection(Option<String>),
+    SendTemplate,
+    CancelTemplate,
+    OpenTemplates,
+    QueueUp(usize),
+    QueueRemove(usize),
+    ChangeModel(String),
     ClearChat,
     ModelPullInput(String),
     BotEvent(stream::Event),
@@ -70,35 +310,132 @@ pub enum Message {
     LoadConversation,
     RemoveConversation,
     PullModel,
+    CancelTransfer(stream::RequestId),
     ModelsDelSelector(usize),
     DelModel,
     OpenImages,
     ImagesResult(Vec<Image>),
+    PasteImage,
+    ImagePasted(Option<Image>),
+    CaptureScreen,
+    ScreenCaptured(Result<Option<Image>, String>),
+    AskAboutClipboard,
+    ClipboardText(Option<String>),
+    Shortcut(Shortcut),
+    SelectionText(Option<String>),
+    KeyPressed(keyboard::Key, keyboard::Modifiers),
+    KeyBindingInput(KeyAction, String),
+    CommitKeyBinding(KeyAction),
+    KeyAction(KeyAction),
     FindAvatar,
     AvatarResult(PathBuf),
+    SelectServer(usize),
+    AddServer,
+    RemoveServer,
+    ServerNameInput(String),
+    SelectBackend(usize),
+    ModelsListed(String, Result<api::Tags, String>),
+    CheckHealth,
+    Health(Result<api::Version, String>),
+    TestConnection,
+    ConnectionTested(Result<api::Version, String>),
     OllamaAdressFlag(bool),
     OllamaAddressInput(String),
     OllamaAddressSend(String),
+    FindCaCertificate,
+    CaCertificateResult(PathBuf),
+    ClearCaCertificate,
+    TokenInput(String),
+    ToggleTokenVisible,
+    HeaderNameInput(usize, String),
+    HeaderValueInput(usize, String),
+    AddHeader,
+    RemoveHeader(usize),
+    SaveCredentials,
+    CredentialsLoaded(Result<(), String>),
+    CredentialsSaved(Result<(), String>),
+    ServerRemoved(Result<(), String>),
     SetKeepAliveTime(String),
     OpenLink(iced::widget::markdown::Url),
+    EditMessage(usize),
+    CancelEdit,
+    Regenerate(usize),
+    ContinueReply(usize),
+    SelectBranch(usize),
+    Fork(usize),
+    ToggleStats(usize),
+    ModelInfo(Option<u64>),
+    CompareModelInfo(String, Option<u64>),
+    Summarized(Result<Summary, String>),
+    ContextStrategy(usize),
+    ContextWindow(String),
+    CommitContextWindow,
+    ExportFormat(usize),
+    ToggleEmbedImages,
+    ExportConversation,
+    ExportAll,
+    ExportDone(String),
+    ImportConversations,
+    ImportPreview(Result<import::Preview, String>),
+    ConfirmImport,
+    CancelImport,
 }
 
 pub struct Window {
     core: Core,
     popup: Option<Id>,
     page: Pages,
-    prompt: String,
+    prompt_editor: text_editor::Content,
+    /// Pasted texts too long to show in the editor.
+    pasted: Vec<String>,
     conversation: Conversation,
     bot_response: String,
     system_messages: Vec<String>,
     models: Vec<String>,
     selected_model: String,
-    model_index: Option<usize>,
-    last_id: usize,
+    /// Everything the server told about its models, for the picker.
+    model_tags: Vec<api::Model>,
+    model_search: String,
+    /// Preset of the selected model.
+    preset: ModelPreset,
+    preset_model: String,
+    preset_draft: PresetDraft,
+    think_options: Vec<String>,
+    /// Models the next prompt is compared across.
+    compare_models: Vec<String>,
+    /// Context lengths the server reports for the compared models.
+    compare_context: HashMap<String, Option<u64>>,
+    comparison: Vec<Candidate>,
+    /// Sender of the stream service, once it is up.
+    requests: Option<mpsc::Sender<stream::Request>>,
+    last_request: stream::RequestId,
+    /// Running pulls and removals: the model and the latest status.
+    transfers: BTreeMap<stream::RequestId, (String, String)>,
     chat_id: id::Id,
+    prompt_id: id::Id,
     keep_context: bool,
-    context: Option<Vec<u64>>,
-    images: Vec<String>,
+    editing: Option<usize>,
+    /// Stopped reply that the running request adds to. The reply is the
+    /// last message sent, which the model picks up from.
+    continuing: Option<usize>,
+    /// Prompts submitted while busy, sent in order.
+    queue: Vec<String>,
+    templates: Vec<Template>,
+    template_form: Option<TemplateForm>,
+    context_length: Option<u64>,
+    context_strategies: Vec<String>,
+    /// Context window as typed, applied on submit.
+    context_window: String,
+    context_used: Cell<Option<(HistoryKey, u64)>>,
+    connection: RefCell<Option<(ConnectionKey, api::Connection)>>,
+    final_response: Option<BotResponse>,
+    /// The running chat request failed; it ends with `Done` all the same.
+    request_failed: bool,
+    sent_tokens: u64,
+    request_started: Option<Instant>,
+    first_token: Option<Duration>,
+    expanded_stats: HashSet<usize>,
+    usage: usage::Aggregates,
     saved_conversations: Vec<String>,
     selected_saved_conv: Option<usize>,
     request: StreamingRequest,
@@ -110,6 +447,17 @@ pub struct Window {
     ollama_address: String,
     ollama_address_edit: bool,
     keep_alive_model: String,
+    export_formats: Vec<String>,
+    export_format: usize,
+    embed_images: bool,
+    import_preview: Option<import::Preview>,
+    server_names: Vec<String>,
+    backends: Vec<String>,
+    server_status: ServerStatus,
+    credentials: secrets::Credentials,
+    token_hidden: bool,
+    /// Bindings being typed, applied on submit.
+    key_binding_drafts: HashMap<KeyAction, String>,
     settings: Settings,
 }
 
@@ -131,51 +479,107 @@ impl Application for Window {
     }
 
     fn init(core: Core, _flags: Self::Flags) -> (Self, Command<Action<Message>>) {
-        let system_messages = Vec::new();
-        let models: Vec<String> = installed_models();
-        let settings = Settings::load();
-        let model_index = models
-            .clone()
-            .into_iter()
-            .position(|model| model == settings.model);
+        let (settings, unreadable) = Settings::load();
+        let mut system_messages = Vec::new();
+        if let Some(reason) = unreadable {
+            system_messages.push(fl!("settings-unreadable", reason = reason));
+        }
+        let model_tags = cached_models(&settings.server().id)
+            .map(|tags| tags.models)
+            .unwrap_or_default();
+        let models: Vec<String> = model_tags.iter().map(|model| model.model.clone()).collect();
         let delete_this_model = if !models.is_empty() {
             models[0].clone()
         } else {
             String::new()
         };
 
-        (
-            Self {
-                core,
-                popup: None,
-                page: Pages::Chat,
-                prompt: String::new(),
-                conversation: Conversation::new(),
-                bot_response: String::new(),
-                system_messages,
-                models: models.clone(),
-                selected_model: settings.model.clone(),
-                model_index,
-                last_id: 0,
-                chat_id: id::Id::new("chat"),
-                keep_context: settings.keep_context,
-                context: None,
-                images: Vec::new(),
-                saved_conversations: Vec::new(),
-                selected_saved_conv: Some(0),
-                request: StreamingRequest::Idle,
-                model_to_pull: String::new(),
-                del_model_index: Some(0),
-                delete_this_model,
-                status_area_status: String::new(),
-                user_avatar: settings.get_avatar_handle(),
-                ollama_address: settings.ollama_address.clone(),
-                ollama_address_edit: false,
-                keep_alive_model: settings.keep_alive_model.clone(),
-                settings,
-            },
-            Command::none(),
-        )
+        let mut window = Self {
+            core,
+            popup: None,
+            page: Pages::Chat,
+            prompt_editor: text_editor::Content::new(),
+            pasted: Vec::new(),
+            conversation: Conversation::new(),
+            bot_response: String::new(),
+            system_messages,
+            models: models.clone(),
+            selected_model: settings.server().model.clone(),
+            model_tags,
+            model_search: String::new(),
+            preset: settings.preset(&settings.server().model),
+            preset_model: String::new(),
+            preset_draft: PresetDraft::default(),
+            compare_models: Vec::new(),
+            compare_context: HashMap::new(),
+            comparison: Vec::new(),
+            think_options: vec![fl!("think-default"), fl!("think-on"), fl!("think-off")],
+            requests: None,
+            last_request: 0,
+            transfers: BTreeMap::new(),
+            chat_id: id::Id::new("chat"),
+            prompt_id: id::Id::new("prompt"),
+            keep_context: settings.keep_context,
+            editing: None,
+            continuing: None,
+            queue: Vec::new(),
+            templates: Vec::new(),
+            template_form: None,
+            context_length: None,
+            context_window: settings.context_window.to_string(),
+            context_used: Cell::new(None),
+            connection: RefCell::new(None),
+            context_strategies: context::Strategy::ALL
+                .iter()
+                .map(|strategy| strategy.name())
+                .collect(),
+            final_response: None,
+            request_failed: false,
+            sent_tokens: 0,
+            request_started: None,
+            first_token: None,
+            expanded_stats: HashSet::new(),
+            usage: usage::Aggregates::default(),
+            saved_conversations: Vec::new(),
+            selected_saved_conv: Some(0),
+            request: StreamingRequest::Idle,
+            model_to_pull: String::new(),
+            del_model_index: Some(0),
+            delete_this_model,
+            status_area_status: String::new(),
+            user_avatar: settings.get_avatar_handle(),
+            ollama_address: settings.server().address.clone(),
+            ollama_address_edit: false,
+            keep_alive_model: settings.server().keep_alive.clone(),
+            export_formats: export::Format::ALL
+                .iter()
+                .map(|format| format.name())
+                .collect(),
+            export_format: 0,
+            embed_images: true,
+            import_preview: None,
+            server_names: settings.server_names(),
+            backends: api::Backend::ALL
+                .iter()
+                .map(|backend| backend.name())
+                .collect(),
+            server_status: ServerStatus::Unknown,
+            credentials: secrets::Credentials::default(),
+            token_hidden: true,
+            key_binding_drafts: HashMap::new(),
+            settings,
+        };
+        window.load_templates();
+        let command = Command::batch([
+            window.fetch_model_info(),
+            window.check_health(),
+            window.refresh_models(),
+            Command::perform(secrets::load(window.settings.server_ids()), |result| {
+                Message::CredentialsLoaded(result.map_err(|why| why.to_string())).into()
+            }),
+        ]);
+
+        (window, command)
     }
 
     fn on_close_requested(&self, id: Id) -> Option<Message> {
@@ -191,7 +595,20 @@ impl Application for Window {
             }
         });
 
-        Subscription::run_with_id(self.last_id, stream)
+        let shortcuts = channel(4, move |mut output| async move {
+            let mut activations = pin!(shortcuts::activations());
+
+            while let Some(shortcut) = activations.next().await {
+                let _res = output.send(Message::Shortcut(shortcut)).await;
+            }
+        });
+
+        Subscription::batch([
+            Subscription::run_with_id("stream-service", stream),
+            Subscription::run_with_id("global-shortcuts", shortcuts),
+            keyboard::on_key_press(|key, modifiers| Some(Message::KeyPressed(key, modifiers))),
+            iced::time::every(HEALTH_INTERVAL).map(|_| Message::CheckHealth),
+        ])
     }
 
     fn update(&mut self, message: Message) -> Command<Action<Message>> {
@@ -199,6 +616,7 @@ impl Application for Window {
 
         match message {
             Message::ChatPage => {
+                self.commit_context_window();
                 self.page = Pages::Chat;
                 commands.push(snap_to(self.chat_id.clone(), RelativeOffset::END));
             }
@@ -207,6 +625,79 @@ impl Application for Window {
 
                 self.saved_conversations = read_conversation_files().unwrap();
             }
+            Message::ModelsPage => {
+                self.commit_context_window();
+                self.page = Pages::Models;
+                self.model_search.clear();
+            }
+            Message::ModelSearch(search) => self.model_search = search,
+            Message::ToggleCompareModel(model) => {
+                if let Some(index) = self.compare_models.iter().position(|item| *item == model) {
+                    self.compare_models.remove(index);
+                } else {
+                    self.compare_models.push(model.clone());
+                    commands.push(self.context_length_of(model.clone()).map(
+                        move |context_length| {
+                            Message::CompareModelInfo(model.clone(), context_length).into()
+                        },
+                    ));
+                }
+            }
+            Message::CompareModelInfo(model, context_length) => {
+                self.compare_context.insert(model, context_length);
+            }
+            Message::ClearCompare => self.compare_models.clear(),
+            Message::PickReply(index) => {
+                if self
+                    .comparison
+                    .get(index)
+                    .is_some_and(|candidate| candidate.done)
+                {
+                    let candidate = self.comparison.remove(index);
+                    self.comparison.clear();
+
+                    self.conversation
+                        .push(Text::Bot(MessageContent::Text(candidate.text)));
+                    if let Some(response) = &candidate.final_response {
+                        self.record_tokens(response);
+                    }
+                    if let (Some(reply), Some(stats)) = (self.conversation.leaf, candidate.stats) {
+                        self.conversation.set_stats(reply, stats);
+                    }
+
+                    self.compare_models.clear();
+                    commands.push(self.update(Message::ChangeModel(candidate.model)));
+                    commands.push(self.send_queued());
+                }
+            }
+            Message::EditPreset(model) => {
+                self.preset_draft = PresetDraft::new(&self.settings.preset(&model));
+                self.preset_model = model;
+                self.page = Pages::Preset;
+            }
+            Message::PresetInput(field, value) => *self.preset_draft.field(field) = value,
+            Message::PresetThink(index) => self.preset_draft.think = index,
+            Message::SavePreset => {
+                self.settings
+                    .set_preset(self.preset_model.clone(), self.preset_draft.preset());
+                let _ = self.settings.save();
+                self.preset_draft = PresetDraft::new(&self.settings.preset(&self.preset_model));
+
+                if self.preset_model == self.selected_model {
+                    self.preset = self.settings.preset(&self.selected_model);
+                    commands.push(self.fetch_model_info());
+                }
+            }
+            Message::ResetPreset => self.preset_draft = PresetDraft::default(),
+            Message::ToggleFavorite(model) => {
+                self.settings.toggle_favorite(model);
+                let _ = self.settings.save();
+            }
+            Message::UsagePage => {
+                self.commit_context_window();
+                self.page = Pages::Usage;
+                self.usage = usage::aggregate(&usage::load());
+            }
             Message::PopupClosed(id) => {
                 if Some(id) == self.popup {
                     self.popup = None;
@@ -227,100 +718,307 @@ impl Application for Window {
                     );
                     popup_settings.positioner.size_limits =
                         iced::Limits::NONE.width(680.0).height(800.0);
-                    return get_popup(popup_settings);
+                    // The editor only exists once the popup does.
+                    return get_popup(popup_settings).chain(self.focus_prompt());
                 });
             }
-            Message::EnterPrompt(prompt) => self.prompt = prompt,
-            Message::SendPrompt(prompt) => {
-                self.conversation
-                    .push(Text::User(MessageContent::Text(prompt)));
-                self.last_id += 1;
+            Message::EditPrompt(action) => match action {
+                text_editor::Action::Edit(text_editor::Edit::Paste(text))
+                    if text.chars().count() > PASTE_CHIP_LENGTH =>
+                {
+                    self.pasted.push(text.to_string());
+                }
+                action => {
+                    let before = self.prompt();
+                    self.prompt_editor.perform(action);
+                    // Picks up edits of the library when a command is started.
+                    if self.prompt().trim_end() == "/" && before.trim_end() != "/" {
+                        self.load_templates();
+                    }
+                }
+            },
+            Message::SubmitPrompt => {
+                let mut prompt = self.prompt().trim_end().to_string();
+                for text in std::mem::take(&mut self.pasted) {
+                    if !prompt.is_empty() {
+                        prompt.push_str("\n\n");
+                    }
+                    prompt.push_str(&format!("```\n{}\n```", text.trim_end()));
+                }
 
-                if !self.keep_context {
-                    self.request = StreamingRequest::Ask
+                if !prompt.trim().is_empty() {
+                    return self.update(Message::SendPrompt(prompt));
+                }
+            }
+            Message::ExpandPasted(index) => {
+                if index < self.pasted.len() {
+                    let text = self.pasted.remove(index);
+                    self.quote(&text);
+                }
+            }
+            Message::RemovePasted(index) => {
+                if index < self.pasted.len() {
+                    self.pasted.remove(index);
+                }
+            }
+            Message::SendPrompt(prompt) => {
+                self.set_prompt(String::new());
+                if let Some(index) = self
+                    .templates
+                    .iter()
+                    .position(|template| prompt.trim() == format!("/{}", template.name))
+                {
+                    return self.update(Message::UseTemplate(index));
+                }
+                // A comparison has to be decided before the conversation goes on.
+                if self.is_idle() && self.comparison.is_empty() {
+                    commands.push(self.submit(prompt));
                 } else {
-                    self.request = StreamingRequest::AskWithContext
+                    self.queue.push(prompt);
+                }
+            }
+            Message::SendQueued => commands.push(self.send_queued()),
+            Message::UseTemplate(index) => {
+                if let Some(template) = self.templates.get(index) {
+                    let mut form = TemplateForm {
+                        index,
+                        values: template
+                            .variables()
+                            .into_iter()
+                            .map(|name| (name, String::new()))
+                            .collect(),
+                        clipboard: String::new(),
+                        selection: String::new(),
+                        reads: 0,
+                    };
+
+                    if template.uses(templates::CLIPBOARD) {
+                        form.reads += 1;
+                        commands.push(
+                            iced::clipboard::read()
+                                .map(|text| Message::TemplateClipboard(text).into()),
+                        );
+                    }
+                    if template.uses(templates::SELECTION) {
+                        form.reads += 1;
+                        commands.push(
+                            iced::clipboard::read_primary()
+                                .map(|text| Message::TemplateSelection(text).into()),
+                        );
+                    }
+
+                    self.set_prompt(String::new());
+                    self.template_form = Some(form);
+                    commands.push(self.send_template_if_ready());
+                }
+            }
+            Message::TemplateInput(index, value) => {
+                if let Some((_, current)) = self
+                    .template_form
+                    .as_mut()
+                    .and_then(|form| form.values.get_mut(index))
+                {
+                    *current = value;
+                }
+            }
+            Message::TemplateClipboard(text) => {
+                if let Some(form) = &mut self.template_form {
+                    form.clipboard = text.unwrap_or_default();
+                    form.reads = form.reads.saturating_sub(1);
+                }
+                commands.push(self.send_template_if_ready());
+            }
+            Message::TemplateSelection(text) => {
+                if let Some(form) = &mut self.template_form {
+                    form.selection = text.unwrap_or_default();
+                    form.reads = form.reads.saturating_sub(1);
+                }
+                commands.push(self.send_template_if_ready());
+            }
+            Message::SendTemplate => {
+                if let Some(form) = self.template_form.take() {
+                    if let Some(template) = self.templates.get(form.index) {
+                        let prompt = template.render(&form.values());
+                        commands.push(self.update(Message::SendPrompt(prompt)));
+                    }
+                }
+            }
+            Message::CancelTemplate => self.template_form = None,
+            Message::OpenTemplates => {
+                let _ = open::that_in_background(templates::folder());
+            }
+            Message::QueueUp(index) => {
+                if index > 0 && index < self.queue.len() {
+                    self.queue.swap(index - 1, index);
+                }
+            }
+            Message::QueueRemove(index) => {
+                if index < self.queue.len() {
+                    self.queue.remove(index);
                 }
             }
             Message::BotEvent(ev) => match ev {
-                stream::Event::Ready(tx) => {
-                    match self.request {
-                        StreamingRequest::Ask => {
-                            _ = tx.blocking_send(stream::Request::Ask((
-                                self.selected_model.clone(),
-                                self.prompt.clone(),
-                                self.images.clone(),
-                                self.keep_alive_model.clone(),
-                            )))
+                stream::Event::Ready(tx) => self.requests = Some(tx),
+                // Events of stopped requests may still be on their way and
+                // match nothing.
+                stream::Event::Response(id, message) => {
+                    let started = self.request_started;
+                    if self.is_chat(id) {
+                        if let Some(reply) = &message.message {
+                            if self.first_token.is_none() && !reply.content.is_empty() {
+                                self.first_token = started.map(|started| started.elapsed());
+                            }
+                            self.bot_response.push_str(&reply.content);
                         }
-                        StreamingRequest::AskWithContext => {
-                            _ = tx.blocking_send(stream::Request::AskWithContext((
-                                self.selected_model.clone(),
-                                self.prompt.clone(),
-                                self.images.clone(),
-                                self.context.clone(),
-                                self.keep_alive_model.clone(),
-                            )))
+                        if message.done {
+                            self.final_response = Some(message);
                         }
-                        StreamingRequest::PullModel => {
-                            _ = tx.blocking_send(stream::Request::PullModel(
-                                self.model_to_pull.clone(),
-                            ))
+
+                        commands.push(snap_to(self.chat_id.clone(), RelativeOffset::END));
+                    } else if let Some(candidate) = self.candidate(id) {
+                        if let Some(reply) = &message.message {
+                            if candidate.first_token.is_none() && !reply.content.is_empty() {
+                                candidate.first_token = started.map(|started| started.elapsed());
+                            }
+                            candidate.text.push_str(&reply.content);
                         }
-                        StreamingRequest::RemoveModel => {
-                            _ = tx.blocking_send(stream::Request::RemoveModel(
-                                self.delete_this_model.clone(),
-                            ))
+                        if message.done {
+                            candidate.final_response = Some(message);
                         }
-                        StreamingRequest::Idle => {}
                     }
-                    self.prompt.clear();
-                }
-                stream::Event::Response(message) => {
-                    self.bot_response.push_str(&message.response);
-                    self.context = message.context;
-
-                    commands.push(snap_to(self.chat_id.clone(), RelativeOffset::END));
                 }
-                stream::Event::Done => {
-                    self.conversation
-                        .push(Text::Bot(MessageContent::Text(self.bot_response.clone())));
-                    self.bot_response.clear();
-                    self.images.clear();
-                    self.request = StreamingRequest::Idle;
+                stream::Event::Failed(id, why) => {
+                    if let Some(candidate) = self.candidate(id) {
+                        candidate.error = Some(why);
+                    } else if let Some((model, _)) = self.transfers.get(&id) {
+                        self.status_area_status = format!("{}: {}", model, why);
+                    } else if self.is_chat(id) {
+                        self.request_failed = true;
+                        self.system_messages
+                            .push(fl!("request-failed", reason = why));
+                    }
                 }
-                stream::Event::PullResponse(status) => {
-                    self.status_area_status = status.status;
+                stream::Event::Done(id) => {
+                    let elapsed = self.request_started.map(|started| started.elapsed());
+                    if self.is_chat(id) {
+                        // A reply cut short by a failure is kept as stopped, so
+                        // that it can be continued or regenerated.
+                        let complete = !self.request_failed && self.final_response.is_some();
+                        if let Some(reply) = self.continuing.take() {
+                            self.conversation
+                                .append_text(reply, &self.bot_response)
+                                .set_stopped(reply, !complete);
+                        } else if !self.bot_response.is_empty() {
+                            self.conversation
+                                .push(Text::Bot(MessageContent::Text(self.bot_response.clone())));
+                            if let Some(reply) = self.conversation.leaf {
+                                self.conversation.set_stopped(reply, !complete);
+                            }
+                            if let Some(response) =
+                                self.final_response.as_ref().filter(|_| complete)
+                            {
+                                self.record_tokens(response);
+                            }
+                        }
+                        if let Some(response) = self.final_response.take() {
+                            self.record_stats(&response);
+                        }
+                        self.bot_response.clear();
+                        self.request_failed = false;
+                        self.request = StreamingRequest::Idle;
+                        // Queued prompts wait until the failed one is sorted out.
+                        if complete {
+                            commands.push(self.send_queued());
+                        }
+                    } else if let Some(candidate) = self.candidate(id) {
+                        candidate.finish(elapsed);
+                        if let Some(stats) = &candidate.stats {
+                            let _ = usage::append(&usage::Record::new(&candidate.model, stats));
+                        }
+                        if self.comparison.iter().all(|candidate| candidate.done) {
+                            self.request = StreamingRequest::Idle;
+                        }
+                    }
                 }
-                stream::Event::PullDone => {
-                    self.status_area_status.clear();
-                    self.models = installed_models();
+                stream::Event::PullResponse(id, status) => {
+                    if let Some((_, transfer_status)) = self.transfers.get_mut(&id) {
+                        *transfer_status = status.status;
+                    }
                 }
-                stream::Event::RemovedModel => {
-                    self.status_area_status.clear();
-                    self.models = installed_models();
+                stream::Event::PullDone(id) | stream::Event::RemovedModel(id) => {
+                    self.transfers.remove(&id);
+                    commands.push(self.refresh_models());
                 }
-                stream::Event::RemoveStatus(status) => {
+                stream::Event::RemoveStatus(_, status) => {
                     self.status_area_status = status;
                 }
             },
-            Message::ChangeModel(index) => {
-                self.model_index = Some(index);
-                self.selected_model.clone_from(&self.models[index]);
+            Message::ChangeModel(model) => {
+                self.page = Pages::Chat;
+                self.preset = self.settings.preset(&model);
+                self.selected_model = model;
                 self.settings.set_model(self.selected_model.clone());
                 let _ = self.settings.save();
+                self.context_length = None;
+                commands.push(self.fetch_model_info());
             }
             Message::ClearChat => {
-                self.prompt.clear();
+                self.set_prompt(String::new());
+                self.pasted.clear();
                 self.system_messages.clear();
                 self.conversation = Conversation::new();
+                self.editing = None;
             }
             Message::ToggleContext => {
                 self.keep_context = !self.keep_context;
                 self.settings.change_context(self.keep_context);
                 let _ = self.settings.save();
             }
-            Message::StopBot => self.last_id += 1,
+            Message::ModelInfo(context_length) => {
+                self.context_length = self.preset.options.num_ctx.or(context_length);
+            }
+            Message::Summarized(result) => {
+                match result {
+                    Ok(summary) => {
+                        self.conversation.set_summary(summary);
+                    }
+                    Err(why) => self
+                        .system_messages
+                        .push(fl!("summary-failed", reason = why)),
+                }
+
+                // Stop may have been pressed while summarizing.
+                if let StreamingRequest::Summarize = self.request {
+                    self.send_chat();
+                }
+            }
+            Message::ContextStrategy(index) => {
+                self.settings
+                    .set_context_strategy(context::Strategy::ALL[index]);
+                let _ = self.settings.save();
+            }
+            Message::ContextWindow(turns) => self.context_window = turns,
+            Message::CommitContextWindow => self.commit_context_window(),
+            Message::StopBot => {
+                if let StreamingRequest::Chat(id) = self.request {
+                    self.cancel(id);
+                    self.keep_partial_reply();
+                }
+                let elapsed = self.request_started.map(|started| started.elapsed());
+                for candidate in &self.comparison {
+                    if !candidate.done {
+                        self.cancel(candidate.id);
+                    }
+                }
+                for candidate in self
+                    .comparison
+                    .iter_mut()
+                    .filter(|candidate| !candidate.done)
+                {
+                    candidate.finish(elapsed);
+                }
+                self.request = StreamingRequest::Idle;
+            }
             Message::SaveConversation => {
                 let _ = self.conversation.save_to_file();
             }
@@ -330,9 +1028,17 @@ impl Application for Window {
             Message::LoadConversation => {
                 if let Some(selected_saved_conv) = self.selected_saved_conv {
                     if self.saved_conversations.len() > selected_saved_conv {
-                        self.conversation = load_conversation(
+                        match load_conversation(
                             self.saved_conversations[selected_saved_conv].clone(),
-                        );
+                        ) {
+                            Ok(conversation) => {
+                                self.conversation = conversation;
+                                self.editing = None;
+                            }
+                            Err(why) => self
+                                .system_messages
+                                .push(fl!("load-failed", reason = why.to_string())),
+                        }
                     }
                 }
             }
@@ -348,16 +1054,32 @@ impl Application for Window {
                 self.saved_conversations = read_conversation_files().unwrap();
             }
             Message::PullModel => {
-                self.last_id += 1;
-                self.request = StreamingRequest::PullModel;
+                let model = self.model_to_pull.trim().to_string();
+                if !model.is_empty() {
+                    let request = model.clone();
+                    if let Some(id) = self
+                        .send(|id, connection| stream::Request::PullModel(id, connection, request))
+                    {
+                        self.transfers.insert(id, (model, fl!("pulling")));
+                    }
+                }
             }
             Message::ModelsDelSelector(index) => {
                 self.del_model_index = Some(index);
                 self.delete_this_model.clone_from(&self.models[index]);
             }
             Message::DelModel => {
-                self.last_id += 1;
-                self.request = StreamingRequest::RemoveModel;
+                let model = self.delete_this_model.clone();
+                let request = model.clone();
+                if let Some(id) = self
+                    .send(|id, connection| stream::Request::RemoveModel(id, connection, request))
+                {
+                    self.transfers.insert(id, (model, fl!("removing")));
+                }
+            }
+            Message::CancelTransfer(id) => {
+                self.cancel(id);
+                self.transfers.remove(&id);
             }
             Message::OpenImages => {
                 commands.push(Command::perform(
@@ -389,12 +1111,93 @@ impl Application for Window {
             }
             Message::ImagesResult(result) => {
                 for image in result {
-                    self.images.push(image.base64.clone());
                     self.conversation.push(Text::User(MessageContent::Image(
                         ImageAttachment::Raster(image),
                     )));
                 }
             }
+            Message::PasteImage => commands.push(
+                iced::clipboard::read_data::<ClipboardImage>()
+                    .map(|image| Message::ImagePasted(image.map(|image| image.0)).into()),
+            ),
+            Message::ImagePasted(image) => match image {
+                Some(image) => {
+                    self.conversation.push(Text::User(MessageContent::Image(
+                        ImageAttachment::Raster(image),
+                    )));
+                }
+                None => self.system_messages.push(fl!("clipboard-no-image")),
+            },
+            Message::CaptureScreen => commands.push(Command::perform(capture_screen(), |result| {
+                Message::ScreenCaptured(result.map_err(|why| why.to_string())).into()
+            })),
+            Message::ScreenCaptured(result) => match result {
+                Ok(Some(image)) => {
+                    self.conversation.push(Text::User(MessageContent::Image(
+                        ImageAttachment::Raster(image),
+                    )));
+                }
+                Ok(None) => {}
+                Err(why) => self
+                    .system_messages
+                    .push(fl!("screenshot-failed", reason = why)),
+            },
+            Message::AskAboutClipboard => commands
+                .push(iced::clipboard::read().map(|text| Message::ClipboardText(text).into())),
+            Message::ClipboardText(text) => match text.filter(|text| !text.trim().is_empty()) {
+                Some(text) => self.quote(&text),
+                None => self.system_messages.push(fl!("clipboard-no-text")),
+            },
+            Message::Shortcut(shortcut) => {
+                // Opening the popup focuses the prompt once it is there.
+                let shown = self.popup.is_some();
+                if !shown || shortcut == Shortcut::Toggle {
+                    commands.push(self.update(Message::TogglePopup));
+                } else {
+                    commands.push(self.focus_prompt());
+                }
+                if shortcut == Shortcut::AskAboutSelection {
+                    commands.push(
+                        iced::clipboard::read_primary()
+                            .map(|text| Message::SelectionText(text).into()),
+                    );
+                }
+                self.page = Pages::Chat;
+            }
+            Message::KeyAction(action) => commands.push(self.key_action(action)),
+            Message::KeyPressed(key, modifiers) => {
+                if let Some(action) = KeyAction::ALL.into_iter().find(|action| {
+                    keybindings::matches(&self.settings.key_binding(*action), &key, modifiers)
+                }) {
+                    commands.push(self.key_action(action));
+                }
+            }
+            Message::KeyBindingInput(action, binding) => {
+                self.key_binding_drafts.insert(action, binding);
+            }
+            Message::CommitKeyBinding(action) => {
+                if let Some(draft) = self.key_binding_drafts.get(&action) {
+                    // An emptied field goes back to the default.
+                    let binding = match draft.trim() {
+                        "" => action.default_binding().to_string(),
+                        binding => binding.to_string(),
+                    };
+
+                    match self.check_key_binding(action, &binding) {
+                        Ok(()) => {
+                            self.key_binding_drafts.remove(&action);
+                            self.settings.set_key_binding(action, binding);
+                            let _ = self.settings.save();
+                            self.status_area_status.clear();
+                        }
+                        Err(why) => self.status_area_status = why,
+                    }
+                }
+            }
+            Message::SelectionText(text) => match text.filter(|text| !text.trim().is_empty()) {
+                Some(text) => self.quote(&text),
+                None => self.system_messages.push(fl!("selection-empty")),
+            },
             Message::ModelPullInput(model) => self.model_to_pull = model,
             Message::FindAvatar => commands.push(Command::perform(
                 async move {
@@ -429,11 +1232,170 @@ impl Application for Window {
                 self.settings.set_avatar(path);
                 let _ = self.settings.save();
             }
+            Message::SelectServer(index) => {
+                self.settings.set_active_server(index);
+                let _ = self.settings.save();
+                commands.push(self.apply_server());
+            }
+            Message::AddServer => {
+                let name = fl!("server-name-default", number = self.server_names.len() + 1);
+                self.settings.add_server(ServerProfile::new(name));
+                let _ = self.settings.save();
+                commands.push(self.apply_server());
+            }
+            Message::RemoveServer => {
+                let id = self.settings.server().id.clone();
+                commands.push(Command::perform(secrets::remove(id), |result| {
+                    Message::ServerRemoved(result.map_err(|why| why.to_string())).into()
+                }));
+                self.settings.remove_server();
+                let _ = self.settings.save();
+                commands.push(self.apply_server());
+            }
+            Message::ServerNameInput(name) => {
+                self.settings.set_server_name(name);
+                let _ = self.settings.save();
+                self.server_names = self.settings.server_names();
+            }
+            Message::SelectBackend(index) => {
+                self.settings.set_backend(api::Backend::ALL[index]);
+                let _ = self.settings.save();
+                commands.push(self.apply_server());
+            }
+            Message::ModelsListed(server, result) => {
+                // Only the answer of the server that is still selected counts;
+                // on failure the cached list stays.
+                if let Ok(tags) = result {
+                    if server == self.settings.server().id {
+                        self.set_models(tags);
+                    }
+                }
+            }
+            Message::CheckHealth => commands.push(self.check_health()),
+            Message::Health(result) => {
+                self.server_status = match result {
+                    Ok(version) => ServerStatus::Connected(version),
+                    Err(why) => ServerStatus::Disconnected(why),
+                };
+            }
+            Message::TestConnection => {
+                self.status_area_status = fl!("testing-connection");
+                let connection = self.connection();
+                commands.push(Command::perform(
+                    async move { api::Version::new(connection?).await },
+                    |result| {
+                        Message::ConnectionTested(result.map_err(|why| why.to_string())).into()
+                    },
+                ));
+            }
+            Message::ConnectionTested(result) => {
+                self.status_area_status = match &result {
+                    Ok(version) => fl!(
+                        "connection-ok",
+                        latency = version.latency.as_millis().to_string(),
+                        version = version.version.clone().unwrap_or_else(|| "-".into())
+                    ),
+                    Err(why) => fl!("connection-failed", reason = why.clone()),
+                };
+                self.server_status = match result {
+                    Ok(version) => ServerStatus::Connected(version),
+                    Err(why) => ServerStatus::Disconnected(why),
+                };
+            }
             Message::OllamaAdressFlag(flag) => self.ollama_address_edit = flag,
             Message::OllamaAddressInput(input) => self.ollama_address = input,
             Message::OllamaAddressSend(addr) => {
                 self.settings.set_ollama_address(addr);
                 let _ = self.settings.save();
+                commands.push(self.apply_server());
+            }
+            Message::FindCaCertificate => commands.push(Command::perform(
+                async move {
+                    let result = SelectedFiles::open_file()
+                        .title("Open certificate")
+                        .modal(true)
+                        .multiple(false)
+                        .filter(
+                            FileFilter::new("Certificate")
+                                .glob("*.pem")
+                                .glob("*.crt")
+                                .glob("*.der"),
+                        )
+                        .send()
+                        .await
+                        .and_then(|request| request.response());
+
+                    result
+                        .ok()
+                        .and_then(|files| files.uris().first().cloned())
+                        .and_then(|uri| uri.to_file_path().ok())
+                        .unwrap_or_default()
+                },
+                |path| Message::CaCertificateResult(path).into(),
+            )),
+            Message::CaCertificateResult(path) => {
+                if !path.as_os_str().is_empty() {
+                    self.settings.set_ca_certificate(path);
+                    let _ = self.settings.save();
+                    commands.push(self.apply_server());
+                }
+            }
+            Message::ClearCaCertificate => {
+                self.settings.set_ca_certificate(PathBuf::new());
+                let _ = self.settings.save();
+                commands.push(self.apply_server());
+            }
+            Message::TokenInput(token) => self.credentials.token = token,
+            Message::ToggleTokenVisible => self.token_hidden = !self.token_hidden,
+            Message::HeaderNameInput(index, name) => {
+                if let Some(header) = self.credentials.headers.get_mut(index) {
+                    header.0 = name;
+                }
+            }
+            Message::HeaderValueInput(index, value) => {
+                if let Some(header) = self.credentials.headers.get_mut(index) {
+                    header.1 = value;
+                }
+            }
+            Message::AddHeader => self.credentials.headers.push(Default::default()),
+            Message::RemoveHeader(index) => {
+                if index < self.credentials.headers.len() {
+                    self.credentials.headers.remove(index);
+                }
+            }
+            Message::SaveCredentials => {
+                let mut credentials = self.credentials.clone();
+                credentials
+                    .headers
+                    .retain(|(name, _)| !name.trim().is_empty());
+
+                commands.push(Command::perform(
+                    secrets::store(self.settings.server().id.clone(), credentials),
+                    |result| {
+                        Message::CredentialsSaved(result.map_err(|why| why.to_string())).into()
+                    },
+                ));
+            }
+            Message::CredentialsLoaded(result) => match result {
+                Ok(()) => commands.push(self.apply_server()),
+                Err(why) => {
+                    self.status_area_status = fl!("credentials-failed", reason = why);
+                }
+            },
+            Message::CredentialsSaved(result) => match result {
+                Ok(()) => {
+                    self.status_area_status = fl!("credentials-saved");
+                    commands.push(self.apply_server());
+                }
+                Err(why) => {
+                    self.status_area_status = fl!("credentials-failed", reason = why);
+                }
+            },
+            Message::ServerRemoved(result) => {
+                self.status_area_status = match result {
+                    Ok(()) => fl!("server-removed"),
+                    Err(why) => fl!("credentials-failed", reason = why),
+                };
             }
             Message::SetKeepAliveTime(time_string) => {
                 self.keep_alive_model = time_string;
@@ -444,17 +1406,214 @@ impl Application for Window {
             Message::OpenLink(url) => {
                 let _ = open::that_in_background(url.to_string());
             }
-        };
-
-        Command::batch(commands)
-    }
+            Message::EditMessage(id) => {
+                if let Some(Text::User(MessageContent::Text(text))) =
+                    self.conversation.nodes.get(id).map(|node| &node.text)
+                {
+                    let text = text.clone();
+                    self.set_prompt(text);
+                    self.editing = Some(id);
+                }
+            }
+            Message::CancelEdit => {
+                self.editing = None;
+                self.set_prompt(String::new());
+            }
+            Message::Regenerate(id) => {
+                if self.is_idle() {
+                    self.conversation.rewind(id);
+                    commands.push(self.start_chat());
+                }
+            }
+            Message::ContinueReply(id) => {
+                let stopped = self
+                    .conversation
+                    .nodes
+                    .get(id)
+                    .is_some_and(|node| node.stopped);
+                if self.is_idle() && stopped && self.conversation.leaf == Some(id) {
+                    self.continuing = Some(id);
+                    self.send_chat();
+                }
+            }
+            Message::Fork(id) => {
+                if self.is_idle() {
+                    // A saved original is left as it is on disk. An unsaved one
+                    // is saved under a new name, so that it can be reopened.
+                    let kept = if self.conversation.filename.is_none() {
+                        let saved = self.conversation.save_to_file();
+                        self.saved_conversations = read_conversation_files().unwrap_or_default();
+                        saved
+                    } else {
+                        Ok(())
+                    };
+
+                    match kept {
+                        Ok(()) => {
+                            self.conversation = self.conversation.fork(id);
+                            self.editing = None;
+                            commands.push(snap_to(self.chat_id.clone(), RelativeOffset::END));
+                        }
+                        Err(why) => self
+                            .system_messages
+                            .push(fl!("fork-failed", reason = why.to_string())),
+                    }
+                }
+            }
+            Message::ToggleStats(id) => {
+                if !self.expanded_stats.remove(&id) {
+                    self.expanded_stats.insert(id);
+                }
+            }
+            Message::SelectBranch(id) => {
+                if self.is_idle() {
+                    self.conversation.select(id);
+                    self.editing = None;
+                }
+            }
+            Message::ExportFormat(index) => self.export_format = index,
+            Message::ToggleEmbedImages => self.embed_images = !self.embed_images,
+            Message::ExportConversation => {
+                let conversation = self.conversation.clone();
+                let format = export::Format::ALL[self.export_format];
+                let embed_images = self.embed_images;
 
-    fn view(&self) -> Element<'_, Self::Message> {
-        self.core
-            .applet
-            .icon_button("dev.heppen.ollama-symbolic")
-            .on_press(Message::TogglePopup)
-            .into()
+                commands.push(Command::perform(
+                    async move {
+                        let name = export::file_name(&export::default_name(), format);
+                        let result = SelectedFiles::save_file()
+                            .title("Export conversation")
+                            .accept_label("Export")
+                            .modal(true)
+                            .current_name(name.as_str())
+                            .filter(FileFilter::new(&format.name()).glob(&format.glob()))
+                            .send()
+                            .await
+                            .and_then(|request| request.response());
+
+                        let Some(path) = result
+                            .ok()
+                            .and_then(|files| files.uris().first().cloned())
+                            .and_then(|uri| uri.to_file_path().ok())
+                        else {
+                            return String::new();
+                        };
+
+                        match export::write(&conversation, format, &path, embed_images) {
+                            Ok(()) => fl!("exported", path = path.display().to_string()),
+                            Err(why) => fl!("export-failed", reason = why.to_string()),
+                        }
+                    },
+                    |status| Message::ExportDone(status).into(),
+                ));
+            }
+            Message::ExportAll => {
+                let format = export::Format::ALL[self.export_format];
+                let embed_images = self.embed_images;
+
+                commands.push(Command::perform(
+                    async move {
+                        let result = SelectedFiles::open_file()
+                            .title("Export all conversations")
+                            .accept_label("Export")
+                            .modal(true)
+                            .directory(true)
+                            .send()
+                            .await
+                            .and_then(|request| request.response());
+
+                        let Some(dir) = result
+                            .ok()
+                            .and_then(|files| files.uris().first().cloned())
+                            .and_then(|uri| uri.to_file_path().ok())
+                        else {
+                            return String::new();
+                        };
+
+                        match export::write_all(format, &dir, embed_images) {
+                            Ok(count) => fl!(
+                                "exported-all",
+                                count = count,
+                                path = dir.display().to_string()
+                            ),
+                            Err(why) => fl!("export-failed", reason = why.to_string()),
+                        }
+                    },
+                    |status| Message::ExportDone(status).into(),
+                ));
+            }
+            Message::ExportDone(status) => self.status_area_status = status,
+            Message::ImportConversations => commands.push(Command::perform(
+                async move {
+                    let result = SelectedFiles::open_file()
+                        .title("Import conversations")
+                        .accept_label("Import")
+                        .modal(true)
+                        .multiple(false)
+                        .filter(FileFilter::new("JSON").glob("*.json"))
+                        .send()
+                        .await
+                        .and_then(|request| request.response());
+
+                    let path = result
+                        .ok()
+                        .and_then(|files| files.uris().first().cloned())
+                        .and_then(|uri| uri.to_file_path().ok())?;
+
+                    Some(import::preview(&path).map_err(|why| why.to_string()))
+                },
+                |preview| match preview {
+                    Some(preview) => Message::ImportPreview(preview).into(),
+                    None => Message::CancelImport.into(),
+                },
+            )),
+            Message::ImportPreview(preview) => match preview {
+                Ok(preview) => {
+                    self.status_area_status.clear();
+                    self.import_preview = Some(preview);
+                }
+                Err(why) => {
+                    self.import_preview = None;
+                    self.status_area_status = fl!("import-failed", reason = why);
+                }
+            },
+            Message::ConfirmImport => {
+                if let Some(preview) = self.import_preview.take() {
+                    let committed = import::commit(&preview);
+                    self.status_area_status = if committed.failed.is_empty() {
+                        fl!("imported", count = committed.written)
+                    } else {
+                        let failed: Vec<String> = committed
+                            .failed
+                            .iter()
+                            .map(|(title, why)| format!("{}: {}", title, why))
+                            .collect();
+                        fl!(
+                            "imported-partially",
+                            count = committed.written,
+                            failed = failed.join("; ")
+                        )
+                    };
+                    self.saved_conversations = read_conversation_files().unwrap();
+                }
+            }
+            Message::CancelImport => self.import_preview = None,
+        };
+
+        Command::batch(commands)
+    }
+
+    fn view(&self) -> Element<'_, Self::Message> {
+        let icon = match self.server_status {
+            ServerStatus::Disconnected(_) => "network-offline-symbolic",
+            _ => "dev.heppen.ollama-symbolic",
+        };
+
+        self.core
+            .applet
+            .icon_button(icon)
+            .on_press(Message::TogglePopup)
+            .into()
     }
 
     fn view_window(&self, _id: Id) -> Element<'_, Self::Message> {
@@ -463,10 +1622,14 @@ impl Application for Window {
         let page_view = match self.page {
             Pages::Chat => self.chat_view(),
             Pages::Settings => self.settings_view(),
+            Pages::Usage => self.usage_view(),
+            Pages::Models => self.models_view(),
+            Pages::Preset => self.preset_view(),
         };
 
         let content_list = widget::column()
             .push(padded_control(menu_row))
+            .push(padded_control(self.status_indicator()))
             .push(padded_control(page_view))
             .padding(10);
 
@@ -485,24 +1648,74 @@ impl Window {
             .padding(12.0)
             .width(Length::Fill);
 
+        if let Some(summary) = self.conversation.active_summary() {
+            chat = chat.push(self.summary_bubble(&summary.text));
+        }
+
         chat = chat.push(self.chat_messages(&self.conversation));
 
-        if !self.bot_response.is_empty() {
-            chat = chat.push(self.bot_bubble(self.bot_response.clone()));
+        if !self.bot_response.is_empty() && self.continuing.is_none() {
+            chat = chat.push(self.bot_bubble(self.bot_response.clone(), None));
+        }
+
+        if !self.comparison.is_empty() {
+            chat = chat.push(self.comparison_view());
         }
 
         for message in &self.system_messages {
             chat = chat.push(self.system_bubble(message.to_string()))
         }
 
-        let prompt_input = widget::text_input(fl!("prompt-field"), &self.prompt)
-            .on_input(Message::EnterPrompt)
-            .on_submit(Message::SendPrompt)
+        // The editor takes the keys it handles, so configured bindings are
+        // checked first. Up only edits the last prompt from an empty editor.
+        let bindings: Vec<(KeyAction, String)> = KeyAction::ALL
+            .into_iter()
+            .filter(|action| *action != KeyAction::EditLast || self.prompt().trim().is_empty())
+            .map(|action| (action, self.settings.key_binding(action)))
+            .collect();
+
+        // Enter sends, Shift+Enter starts a new line.
+        let prompt_input = text_editor(&self.prompt_editor)
+            .id(self.prompt_id.clone())
+            .placeholder(fl!("prompt-field"))
+            .on_action(Message::EditPrompt)
+            .key_binding(move |key_press| {
+                if let Some((action, _)) = bindings.iter().find(|(_, binding)| {
+                    keybindings::matches(binding, &key_press.key, key_press.modifiers)
+                }) {
+                    return Some(text_editor::Binding::Custom(Message::KeyAction(*action)));
+                }
+
+                let enter = matches!(
+                    key_press.key,
+                    keyboard::Key::Named(keyboard::key::Named::Enter)
+                );
+
+                if enter && !key_press.modifiers.shift() {
+                    Some(text_editor::Binding::Custom(Message::SubmitPrompt))
+                } else {
+                    text_editor::Binding::from_key_press(key_press)
+                }
+            })
+            .padding(8)
             .width(Length::Fill);
 
         let open_images = widget::button::icon(widget::icon::from_name("mail-attachment-symbolic"))
             .on_press(Message::OpenImages);
 
+        let paste_image = widget::button::icon(widget::icon::from_name("insert-image-symbolic"))
+            .tooltip(fl!("paste-image"))
+            .on_press(Message::PasteImage);
+
+        let capture_screen =
+            widget::button::icon(widget::icon::from_name("applets-screenshooter-symbolic"))
+                .tooltip(fl!("capture-screen"))
+                .on_press(Message::CaptureScreen);
+
+        let ask_clipboard = widget::button::icon(widget::icon::from_name("edit-paste-symbolic"))
+            .tooltip(fl!("ask-about-clipboard"))
+            .on_press(Message::AskAboutClipboard);
+
         let clear_chat = widget::button::icon(widget::icon::from_name("edit-clear-symbolic"))
             .on_press(Message::ClearChat);
 
@@ -513,20 +1726,686 @@ impl Window {
         let fields = widget::row()
             .push(prompt_input)
             .push(open_images)
+            .push(paste_image)
+            .push(capture_screen)
+            .push(ask_clipboard)
             .push(clear_chat)
             .push(stop_bot)
             .spacing(10);
 
-        widget::column()
-            .push(padded_control(
-                widget::Container::new(Scrollable::new(chat).id(self.chat_id.clone()))
-                    .height(Length::Fill),
-            ))
-            .push(padded_control(fields))
+        let mut prompt_area = widget::column().spacing(4);
+        if !self.pasted.is_empty() {
+            prompt_area = prompt_area.push(self.pasted_view());
+        }
+        prompt_area = prompt_area
+            .push(fields)
+            .push(widget::text::caption(self.prompt_counter()));
+
+        let mut header = widget::row().spacing(20);
+
+        let total = self.conversation.total_stats();
+        if total.eval_count.is_some() {
+            header = header.push(widget::text::caption(fl!(
+                "stats-conversation-total",
+                prompt = total.prompt_eval_count.unwrap_or_default(),
+                response = total.eval_count.unwrap_or_default(),
+                duration = seconds(total.total_duration.unwrap_or_default())
+            )));
+        }
+
+        if let Some(context_length) = self.context_length {
+            let used = self.context_used();
+
+            header = header.push(widget::text::caption(fl!(
+                "context-usage",
+                used = used,
+                length = context_length
+            )));
+        }
+
+        let mut content = widget::column().push(padded_control(header));
+
+        if let StreamingRequest::Summarize = self.request {
+            chat = chat.push(self.system_bubble(fl!("summarizing")));
+        }
+
+        content = content.push(padded_control(
+            widget::Container::new(Scrollable::new(chat).id(self.chat_id.clone()))
+                .height(Length::Fill),
+        ));
+
+        if !self.compare_models.is_empty() {
+            let label = if self.comparing() {
+                fl!("comparing", models = self.compare_models.join(", "))
+            } else {
+                fl!("compare-pick-more")
+            };
+
+            content = content.push(padded_control(
+                widget::row()
+                    .push(widget::text(label).width(Length::Fill))
+                    .push(
+                        widget::button::standard(fl!("cancel"))
+                            .on_press_maybe(self.is_idle().then_some(Message::ClearCompare)),
+                    )
+                    .align_y(iced::Alignment::Center)
+                    .spacing(10),
+            ));
+        }
+
+        if !self.queue.is_empty() {
+            content = content.push(padded_control(self.queue_view()));
+        }
+
+        if let Some(form) = &self.template_form {
+            content = content.push(padded_control(self.template_form_view(form)));
+        } else {
+            let completions = templates::complete(&self.templates, self.prompt().trim_end());
+            if !completions.is_empty() {
+                content = content.push(padded_control(self.completions_view(completions)));
+            }
+        }
+
+        if self.editing.is_some() {
+            content = content.push(padded_control(
+                widget::row()
+                    .push(widget::text(fl!("editing-message")).width(Length::Fill))
+                    .push(widget::button::standard(fl!("cancel")).on_press(Message::CancelEdit))
+                    .align_y(iced::Alignment::Center)
+                    .spacing(10),
+            ));
+        }
+
+        content
+            .push(padded_control(prompt_area))
             .height(Length::Fill)
             .into()
     }
 
+    /// Messages sent with the next request, fitted into the model's context.
+    /// Without kept context only the latest prompt goes out.
+    fn history(&self) -> Vec<Turn> {
+        self.history_for(self.context_length)
+    }
+
+    /// The history fitted into a model with the given context length.
+    fn history_for(&self, context_length: Option<u64>) -> Vec<Turn> {
+        let mut turns = context::fit(
+            self.conversation.turns(),
+            self.conversation.active_summary(),
+            self.settings.context_strategy,
+            self.settings.context_window,
+            context_length,
+        );
+
+        if !self.keep_context {
+            turns = turns.split_off(turns.len().saturating_sub(1));
+
+            // A continued reply needs the prompt it answers.
+            if self.continuing.is_some() {
+                let prompt = self.conversation.turns().into_iter().rev().nth(1);
+                if let Some(prompt) = prompt.filter(|turn| turn.message.role == Role::User) {
+                    turns.insert(0, prompt);
+                }
+            }
+        }
+
+        turns
+    }
+
+    /// Tokens of the history, cached because fitting it clones every
+    /// attached image and the view asks on every redraw.
+    fn context_used(&self) -> u64 {
+        let key = (
+            self.conversation.revision(),
+            self.keep_context,
+            self.continuing.is_some(),
+            self.settings.context_strategy,
+            self.settings.context_window,
+            self.context_length,
+        );
+
+        match self.context_used.get() {
+            Some((cached, used)) if cached == key => used,
+            _ => {
+                let used = self.history().iter().map(|turn| turn.tokens).sum();
+                self.context_used.set(Some((key, used)));
+                used
+            }
+        }
+    }
+
+    /// Applies the typed context window; anything unparsable is reset.
+    fn commit_context_window(&mut self) {
+        if let Ok(turns) = self.context_window.trim().parse::<usize>() {
+            self.settings.set_context_window(turns.max(1));
+            let _ = self.settings.save();
+        }
+        self.context_window = self.settings.context_window.to_string();
+    }
+
+    /// Rejects bindings that cannot be parsed or are taken by another action.
+    fn check_key_binding(&self, action: KeyAction, binding: &str) -> Result<(), String> {
+        let parsed = keybindings::parse(binding)
+            .ok_or_else(|| fl!("key-binding-invalid", binding = binding.to_string()))?;
+
+        match KeyAction::ALL.into_iter().find(|other| {
+            *other != action
+                && keybindings::parse(&self.settings.key_binding(*other)).as_ref() == Some(&parsed)
+        }) {
+            Some(other) => Err(fl!(
+                "key-binding-taken",
+                binding = binding.to_string(),
+                action = other.name()
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn key_action(&mut self, action: KeyAction) -> Command<Action<Message>> {
+        match action {
+            KeyAction::Stop => {
+                if !self.is_idle() {
+                    self.update(Message::StopBot)
+                } else if self.template_form.is_some() {
+                    self.update(Message::CancelTemplate)
+                } else if self.popup.is_some() {
+                    self.update(Message::TogglePopup)
+                } else {
+                    Command::none()
+                }
+            }
+            KeyAction::NewChat => {
+                self.page = Pages::Chat;
+                self.update(Message::ClearChat)
+            }
+            KeyAction::ClearChat => self.update(Message::ClearChat),
+            KeyAction::SaveConversation => self.update(Message::SaveConversation),
+            KeyAction::Settings => self.update(Message::SettingsPage),
+            KeyAction::EditLast => {
+                let last = self
+                    .conversation
+                    .branch()
+                    .into_iter()
+                    .rev()
+                    .find(|node| matches!(node.text, Text::User(MessageContent::Text(_))))
+                    .map(|node| node.id);
+
+                match last.filter(|_| self.prompt().trim().is_empty() && self.is_idle()) {
+                    Some(id) => self.update(Message::EditMessage(id)),
+                    None => Command::none(),
+                }
+            }
+            KeyAction::CopyLastReply => {
+                let last =
+                    self.conversation
+                        .branch()
+                        .into_iter()
+                        .rev()
+                        .find_map(|node| match &node.text {
+                            Text::Bot(MessageContent::Text(text)) => Some(text.clone()),
+                            _ => None,
+                        });
+
+                match last {
+                    Some(text) => iced::clipboard::write(text),
+                    None => Command::none(),
+                }
+            }
+        }
+    }
+
+    /// Adds text to the prompt as a fenced block to ask about.
+    fn quote(&mut self, text: &str) {
+        let mut prompt = self.prompt().trim_end().to_string();
+        if !prompt.is_empty() {
+            prompt.push_str("\n\n");
+        }
+        prompt.push_str(&format!("```\n{}\n```\n", text.trim_end()));
+
+        self.set_prompt(prompt);
+    }
+
+    /// Replaces the prompt, keeping the editor in step.
+    fn set_prompt(&mut self, prompt: String) {
+        self.prompt_editor = text_editor::Content::with_text(&prompt);
+    }
+
+    fn prompt(&self) -> String {
+        self.prompt_editor.text()
+    }
+
+    /// Focuses the prompt editor of the popup.
+    fn focus_prompt(&self) -> Command<Action<Message>> {
+        cosmic::iced_runtime::task::widget(cosmic::iced_core::widget::operation::focusable::focus(
+            self.prompt_id.clone(),
+        ))
+    }
+
+    /// Templates without variables go out as soon as the clipboard is read.
+    fn send_template_if_ready(&mut self) -> Command<Action<Message>> {
+        let ready = self
+            .template_form
+            .as_ref()
+            .is_some_and(|form| form.reads == 0 && form.values.is_empty());
+
+        if ready {
+            self.update(Message::SendTemplate)
+        } else {
+            Command::none()
+        }
+    }
+
+    fn submit(&mut self, prompt: String) -> Command<Action<Message>> {
+        if let Some(id) = self.editing.take() {
+            self.conversation.rewind(id);
+        }
+
+        self.conversation
+            .push(Text::User(MessageContent::Text(prompt)));
+        self.start_chat()
+    }
+
+    /// Sends the oldest queued prompt once nothing else is running. Prompts
+    /// stay queued after a stop until sent by hand.
+    fn send_queued(&mut self) -> Command<Action<Message>> {
+        if !self.is_idle() || !self.comparison.is_empty() || self.queue.is_empty() {
+            return Command::none();
+        }
+
+        let prompt = self.queue.remove(0);
+        self.submit(prompt)
+    }
+
+    /// Starts a chat request, first folding older turns into the summary if
+    /// the history outgrew the context and the strategy asks for it.
+    fn start_chat(&mut self) -> Command<Action<Message>> {
+        if self.keep_context && self.settings.context_strategy == context::Strategy::Summarize {
+            let summary = self.conversation.active_summary();
+
+            if let Some(turns) =
+                context::to_summarize(&self.conversation.turns(), summary, self.context_length)
+            {
+                let previous = summary.map(|summary| summary.text.clone());
+                let connection = self.connection();
+                let model = self.selected_model.clone();
+                let keep_alive = self.keep_alive_model.clone();
+                self.request = StreamingRequest::Summarize;
+
+                return Command::perform(
+                    async move {
+                        context::summarize(connection?, model, previous, turns, keep_alive).await
+                    },
+                    |result| Message::Summarized(result.map_err(|why| why.to_string())).into(),
+                );
+            }
+        }
+
+        self.send_chat();
+        Command::none()
+    }
+
+    /// Sends the history to the selected model, or to every model of the
+    /// comparison.
+    fn send_chat(&mut self) {
+        let history = self.history();
+        self.sent_tokens = history.iter().rev().skip(1).map(|turn| turn.tokens).sum();
+        self.request_started = Some(Instant::now());
+        self.request_failed = false;
+
+        if self.comparing() {
+            self.comparison.clear();
+            for model in self.compare_models.clone() {
+                let preset = self.settings.preset(&model);
+                let context_length = preset
+                    .options
+                    .num_ctx
+                    .or_else(|| self.compare_context.get(&model).copied().flatten());
+                let messages = request_messages(&preset, &self.history_for(context_length));
+                let parameters = self.parameters(&preset);
+                let request = model.clone();
+
+                if let Some(id) = self.send(|id, connection| {
+                    stream::Request::Chat(id, connection, request, messages, parameters)
+                }) {
+                    self.comparison.push(Candidate::new(id, model));
+                }
+            }
+
+            self.request = if self.comparison.is_empty() {
+                StreamingRequest::Idle
+            } else {
+                StreamingRequest::Compare
+            };
+        } else {
+            self.sent_tokens += context::estimate(&self.preset.system_prompt);
+            self.first_token = None;
+
+            let model = self.selected_model.clone();
+            let messages = request_messages(&self.preset, &history);
+            let parameters = self.parameters(&self.preset);
+
+            self.request = match self.send(|id, connection| {
+                stream::Request::Chat(id, connection, model, messages, parameters)
+            }) {
+                Some(id) => StreamingRequest::Chat(id),
+                None => StreamingRequest::Idle,
+            };
+        }
+    }
+
+    /// Keeps what a stopped request generated so far, marked as stopped so
+    /// that it can be continued.
+    fn keep_partial_reply(&mut self) {
+        let partial = std::mem::take(&mut self.bot_response);
+        self.final_response = None;
+
+        if let Some(reply) = self.continuing.take() {
+            self.conversation.append_text(reply, &partial);
+        } else if !partial.is_empty() {
+            self.conversation
+                .push(Text::Bot(MessageContent::Text(partial)));
+            if let Some(reply) = self.conversation.leaf {
+                self.conversation.set_stopped(reply, true);
+            }
+        }
+    }
+
+    fn record_stats(&mut self, response: &BotResponse) {
+        if let Some(reply) = self.conversation.leaf {
+            let mut stats = Stats::from(response);
+            stats.time_to_first_token = self
+                .first_token
+                .take()
+                .map(|duration| duration.as_nanos() as u64);
+            let _ = usage::append(&usage::Record::new(&self.selected_model, &stats));
+            self.conversation.set_stats(reply, stats);
+        }
+    }
+
+    /// Picks up the address, keep-alive, models and selected model of the
+    /// active server profile.
+    fn apply_server(&mut self) -> Command<Action<Message>> {
+        let server = self.settings.server();
+
+        self.ollama_address.clone_from(&server.address);
+        self.keep_alive_model.clone_from(&server.keep_alive);
+        self.selected_model.clone_from(&server.model);
+        self.preset = self.settings.preset(&server.model);
+        self.server_names = self.settings.server_names();
+        self.credentials = secrets::get(&server.id);
+        let tags = cached_models(&server.id).unwrap_or_default();
+
+        self.set_models(tags);
+        self.context_length = None;
+        self.compare_context.clear();
+        self.server_status = ServerStatus::Unknown;
+
+        Command::batch([
+            self.fetch_model_info(),
+            self.check_health(),
+            self.refresh_models(),
+        ])
+    }
+
+    fn set_models(&mut self, tags: api::Tags) {
+        self.models = names(&tags);
+        self.model_tags = tags.models;
+        self.del_model_index = Some(0);
+        self.delete_this_model = self.models.first().cloned().unwrap_or_default();
+    }
+
+    fn refresh_models(&self) -> Command<Action<Message>> {
+        let server = self.settings.server().id.clone();
+        let connection = self.connection();
+        let cache = server.clone();
+
+        Command::perform(
+            async move { installed_models(connection?, cache).await },
+            move |result| {
+                Message::ModelsListed(server.clone(), result.map_err(|why| why.to_string())).into()
+            },
+        )
+    }
+
+    /// Request parameters from a model's preset, falling back to the server
+    /// profile.
+    fn parameters(&self, preset: &ModelPreset) -> api::Parameters {
+        api::Parameters {
+            keep_alive: preset
+                .keep_alive
+                .clone()
+                .unwrap_or_else(|| self.keep_alive_model.clone()),
+            options: preset.options.clone(),
+            think: preset.think,
+        }
+    }
+
+    /// With two or more models picked for comparison, prompts go to all of
+    /// them instead of the selected one.
+    fn comparing(&self) -> bool {
+        self.compare_models.len() > 1
+    }
+
+    fn check_health(&self) -> Command<Action<Message>> {
+        let connection = self.connection();
+
+        Command::perform(
+            async move { api::Version::new(connection?).await },
+            |result| Message::Health(result.map_err(|why| why.to_string())).into(),
+        )
+    }
+
+    fn status_indicator(&self) -> Element<'_, Message> {
+        let (icon, text) = match &self.server_status {
+            ServerStatus::Unknown => ("content-loading-symbolic", fl!("status-connecting")),
+            ServerStatus::Connected(version) => (
+                "emblem-ok-symbolic",
+                match &version.version {
+                    Some(number) => fl!("status-connected-version", version = number.clone()),
+                    None => fl!("status-connected"),
+                },
+            ),
+            ServerStatus::Disconnected(_) => {
+                ("network-offline-symbolic", fl!("status-disconnected"))
+            }
+        };
+
+        let mut indicator = widget::row()
+            .push(widget::icon::from_name(icon).size(16))
+            .push(widget::text::caption(text))
+            .align_y(iced::Alignment::Center)
+            .spacing(6);
+
+        if let ServerStatus::Disconnected(why) = &self.server_status {
+            indicator = indicator.push(
+                widget::text::caption(why.clone()).class(theme::Text::Color(Palette::DARK.danger)),
+            );
+        }
+
+        indicator.into()
+    }
+
+    fn fetch_model_info(&self) -> Command<Action<Message>> {
+        self.context_length_of(self.selected_model.clone())
+            .map(|context_length| Message::ModelInfo(context_length).into())
+    }
+
+    fn context_length_of(&self, model: String) -> Command<Option<u64>> {
+        let connection = self.connection();
+
+        Command::perform(
+            async move {
+                ShowModel::new(connection.ok()?, model)
+                    .await
+                    .ok()
+                    .and_then(|info| info.context_length)
+            },
+            std::convert::identity,
+        )
+    }
+
+    /// Stores the server's token counts on the reply that was just added and
+    /// on the prompt that led to it. The prompt count covers everything that
+    /// had to be evaluated, so the part the earlier turns don't explain is
+    /// attributed to the prompt.
+    fn record_tokens(&mut self, response: &BotResponse) {
+        let Some(reply) = self.conversation.leaf else {
+            return;
+        };
+        self.conversation.set_tokens(reply, response.eval_count);
+
+        let prompt = self.conversation.nodes[reply].parent;
+        if let (Some(prompt), Some(count)) = (prompt, response.prompt_eval_count) {
+            if count > self.sent_tokens {
+                self.conversation
+                    .set_tokens(prompt, Some(count - self.sent_tokens));
+            }
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        matches!(self.request, StreamingRequest::Idle)
+    }
+
+    fn is_chat(&self, id: stream::RequestId) -> bool {
+        matches!(self.request, StreamingRequest::Chat(chat) if chat == id)
+    }
+
+    fn candidate(&mut self, id: stream::RequestId) -> Option<&mut Candidate> {
+        self.comparison
+            .iter_mut()
+            .find(|candidate| candidate.id == id)
+    }
+
+    /// Keeps the previous library when the file cannot be read, and says why.
+    fn load_templates(&mut self) {
+        match templates::load() {
+            Ok(templates) => self.templates = templates,
+            Err(why) => {
+                let message = fl!("templates-failed", reason = why.to_string());
+                if !self.system_messages.contains(&message) {
+                    self.system_messages.push(message);
+                }
+            }
+        }
+    }
+
+    /// How to reach the active server profile. The connection is kept while
+    /// the profile's address and credentials stay the same, so that requests
+    /// share its client.
+    fn connection(&self) -> anyhow::Result<api::Connection> {
+        let server = self.settings.server();
+        let key = (
+            server.backend,
+            server.address.clone(),
+            server.ca_certificate.clone(),
+            secrets::get(&server.id),
+        );
+
+        let mut cached = self.connection.borrow_mut();
+        if let Some((cached_key, connection)) = cached.as_ref() {
+            if *cached_key == key {
+                return Ok(connection.clone());
+            }
+        }
+
+        let connection = api::Connection::new(server)?;
+        *cached = Some((key, connection.clone()));
+        Ok(connection)
+    }
+
+    /// Hands a request to the stream service under a new id.
+    fn send(
+        &mut self,
+        request: impl FnOnce(stream::RequestId, api::Connection) -> stream::Request,
+    ) -> Option<stream::RequestId> {
+        let connection = match self.connection() {
+            Ok(connection) => connection,
+            Err(why) => {
+                self.system_messages
+                    .push(fl!("request-failed", reason = why.to_string()));
+                return None;
+            }
+        };
+        let id = self.last_request + 1;
+        self.requests
+            .as_ref()?
+            .blocking_send(request(id, connection))
+            .ok()?;
+        self.last_request = id;
+
+        Some(id)
+    }
+
+    fn cancel(&self, id: stream::RequestId) {
+        if let Some(requests) = &self.requests {
+            let _ = requests.blocking_send(stream::Request::Cancel(id));
+        }
+    }
+
+    fn message_action(
+        &self,
+        icon: &'static str,
+        tooltip: String,
+        message: Option<Message>,
+    ) -> Element<'_, Message> {
+        widget::button::icon(widget::icon::from_name(icon))
+            .extra_small()
+            .tooltip(tooltip)
+            .on_press_maybe(message)
+            .into()
+    }
+
+    /// An action that changes the conversation, unavailable while a reply
+    /// is generated.
+    fn editing_action(
+        &self,
+        icon: &'static str,
+        tooltip: String,
+        message: Message,
+    ) -> Element<'_, Message> {
+        self.message_action(icon, tooltip, self.is_idle().then_some(message))
+    }
+
+    /// "< 2/3 >" navigation between alternate versions of a message.
+    fn branch_switcher(&self, id: usize) -> Option<Element<'_, Message>> {
+        let siblings = self.conversation.siblings(id);
+        if siblings.len() < 2 {
+            return None;
+        }
+
+        let position = siblings.iter().position(|sibling| *sibling == id)?;
+        let previous = position
+            .checked_sub(1)
+            .map(|index| Message::SelectBranch(siblings[index]));
+        let next = siblings
+            .get(position + 1)
+            .map(|sibling| Message::SelectBranch(*sibling));
+        let idle = self.is_idle();
+
+        Some(
+            widget::row()
+                .push(
+                    widget::button::icon(widget::icon::from_name("go-previous-symbolic"))
+                        .extra_small()
+                        .on_press_maybe(previous.filter(|_| idle)),
+                )
+                .push(widget::text::caption(format!(
+                    "{}/{}",
+                    position + 1,
+                    siblings.len()
+                )))
+                .push(
+                    widget::button::icon(widget::icon::from_name("go-next-symbolic"))
+                        .extra_small()
+                        .on_press_maybe(next.filter(|_| idle)),
+                )
+                .align_y(iced::Alignment::Center)
+                .into(),
+        )
+    }
+
     //noinspection ALL
     fn settings_view(&self) -> Element<'_, Message> {
         let conv_section = settings::section::section()
@@ -539,6 +2418,22 @@ impl Window {
                 fl!("keep-context"),
                 widget::toggler(self.keep_context).on_toggle(|_| Message::ToggleContext),
             ))
+            .add(settings::item(
+                fl!("context-strategy"),
+                widget::dropdown(
+                    &self.context_strategies,
+                    context::Strategy::ALL
+                        .iter()
+                        .position(|strategy| *strategy == self.settings.context_strategy),
+                    Message::ContextStrategy,
+                ),
+            ))
+            .add(settings::item(
+                fl!("context-window"),
+                widget::text_input("10", &self.context_window)
+                    .on_input(Message::ContextWindow)
+                    .on_submit(|_| Message::CommitContextWindow),
+            ))
             .add(settings::item(
                 fl!("select-conversation"),
                 widget::dropdown(
@@ -560,7 +2455,55 @@ impl Window {
                 widget::button::standard(fl!("remove")).on_press(Message::RemoveConversation),
             ));
 
-        let models_section = settings::section()
+        let export_section = settings::section()
+            .title(fl!("export"))
+            .add(settings::item(
+                fl!("export-format"),
+                widget::dropdown(
+                    &self.export_formats,
+                    Some(self.export_format),
+                    Message::ExportFormat,
+                ),
+            ))
+            .add(settings::item(
+                fl!("embed-images"),
+                widget::toggler(self.embed_images).on_toggle(|_| Message::ToggleEmbedImages),
+            ))
+            .add(settings::item(
+                fl!("export-conversation"),
+                widget::button::standard(fl!("export")).on_press(Message::ExportConversation),
+            ))
+            .add(settings::item(
+                fl!("export-all-conversations"),
+                widget::button::standard(fl!("export-all")).on_press(Message::ExportAll),
+            ));
+
+        let mut import_section = settings::section().title(fl!("import")).add(settings::item(
+            fl!("import-conversations"),
+            widget::button::standard(fl!("import")).on_press(Message::ImportConversations),
+        ));
+
+        if let Some(preview) = &self.import_preview {
+            import_section = import_section
+                .add(widget::text(fl!(
+                    "import-summary",
+                    source = preview.source.name(),
+                    conversations = preview.conversations.len(),
+                    messages = preview.messages,
+                    images = preview.images,
+                    duplicates = preview.duplicates
+                )))
+                .add(settings::item_row(vec![
+                    widget::button::suggested(fl!("import"))
+                        .on_press(Message::ConfirmImport)
+                        .into(),
+                    widget::button::standard(fl!("cancel"))
+                        .on_press(Message::CancelImport)
+                        .into(),
+                ]));
+        }
+
+        let mut models_section = settings::section()
             .title(fl!("manage-models"))
             .add(settings::item_row(vec![
                 widget::text_input("llava:latest", &self.model_to_pull)
@@ -582,9 +2525,44 @@ impl Window {
                 widget::button::standard(fl!("remove-model"))
                     .on_press(Message::DelModel)
                     .into(),
+            ]));
+
+        for (id, (model, status)) in &self.transfers {
+            models_section = models_section.add(settings::item_row(vec![
+                widget::text(model.clone()).width(Length::Fill).into(),
+                widget::text::monotext(status.clone()).into(),
+                widget::button::standard(fl!("cancel"))
+                    .on_press(Message::CancelTransfer(*id))
+                    .into(),
+            ]));
+        }
+
+        let ca_certificate = &self.settings.server().ca_certificate;
+        let backend = self.settings.server().backend;
+        let mut servers_section = settings::section()
+            .title(fl!("servers"))
+            .add(settings::item_row(vec![
+                widget::dropdown(
+                    &self.server_names,
+                    Some(self.settings.active_server),
+                    Message::SelectServer,
+                )
+                .width(Length::Fill)
+                .into(),
+                widget::button::standard(fl!("add-server"))
+                    .on_press(Message::AddServer)
+                    .into(),
+                widget::button::standard(fl!("remove-server"))
+                    .on_press_maybe((self.server_names.len() > 1).then_some(Message::RemoveServer))
+                    .into(),
             ]))
+            .add(settings::item(
+                fl!("server-name"),
+                widget::text_input("Local", &self.settings.server().name)
+                    .on_input(Message::ServerNameInput),
+            ))
             .add(settings::item_row(vec![widget::editable_input(
-                "IP:PORT",
+                "https://host:port",
                 &self.ollama_address,
                 self.ollama_address_edit,
                 Message::OllamaAdressFlag,
@@ -593,22 +2571,133 @@ impl Window {
             .on_submit(Message::OllamaAddressSend)
             .into()]))
             .add(settings::item(
+                fl!("backend"),
+                widget::dropdown(
+                    &self.backends,
+                    api::Backend::ALL.iter().position(|item| *item == backend),
+                    Message::SelectBackend,
+                ),
+            ));
+
+        if backend.keeps_alive() {
+            servers_section = servers_section.add(settings::item(
                 fl!("keep-alive"),
                 widget::text_input("5m", &self.keep_alive_model)
                     .on_input(Message::SetKeepAliveTime),
             ));
+        }
+
+        servers_section = servers_section
+            .add(settings::item(
+                fl!("ca-certificate"),
+                widget::row()
+                    .push(widget::text::caption(
+                        ca_certificate
+                            .file_name()
+                            .map(|name| name.to_string_lossy().into_owned())
+                            .unwrap_or_else(|| fl!("none")),
+                    ))
+                    .push(
+                        widget::button::icon(widget::icon::from_name("edit-clear-symbolic"))
+                            .extra_small()
+                            .on_press_maybe(
+                                (!ca_certificate.as_os_str().is_empty())
+                                    .then_some(Message::ClearCaCertificate),
+                            ),
+                    )
+                    .push(
+                        widget::button::standard(fl!("open")).on_press(Message::FindCaCertificate),
+                    )
+                    .align_y(iced::Alignment::Center)
+                    .spacing(8),
+            ))
+            .add(settings::item(
+                fl!("bearer-token"),
+                widget::secure_input(
+                    "",
+                    &self.credentials.token,
+                    Some(Message::ToggleTokenVisible),
+                    self.token_hidden,
+                )
+                .on_input(Message::TokenInput),
+            ));
+
+        for (index, (name, value)) in self.credentials.headers.iter().enumerate() {
+            servers_section = servers_section.add(settings::item_row(vec![
+                widget::text_input(fl!("header-name"), name)
+                    .on_input(move |name| Message::HeaderNameInput(index, name))
+                    .width(Length::FillPortion(1))
+                    .into(),
+                widget::text_input(fl!("header-value"), value)
+                    .on_input(move |value| Message::HeaderValueInput(index, value))
+                    .width(Length::FillPortion(2))
+                    .into(),
+                widget::button::icon(widget::icon::from_name("list-remove-symbolic"))
+                    .extra_small()
+                    .on_press(Message::RemoveHeader(index))
+                    .into(),
+            ]));
+        }
 
-        let spacer = widget::Space::with_height(Length::Fill);
+        servers_section = servers_section.add(settings::item_row(vec![
+            widget::button::standard(fl!("add-header"))
+                .on_press(Message::AddHeader)
+                .into(),
+            widget::button::standard(fl!("test-connection"))
+                .on_press(Message::TestConnection)
+                .into(),
+            widget::Space::with_width(Length::Fill).into(),
+            widget::button::suggested(fl!("save-credentials"))
+                .on_press(Message::SaveCredentials)
+                .into(),
+        ]));
 
         let status_area = widget::row()
             .push(widget::text::monotext("Status: "))
             .push(widget::text::monotext(self.status_area_status.clone()))
             .spacing(10);
 
-        let mut content = widget::column()
+        let mut keys_section = settings::section().title(fl!("keyboard-shortcuts"));
+        for action in KeyAction::ALL {
+            keys_section = keys_section.add(settings::item(
+                action.name(),
+                widget::text_input(
+                    action.default_binding(),
+                    self.key_binding_drafts
+                        .get(&action)
+                        .cloned()
+                        .unwrap_or_else(|| self.settings.key_binding(action)),
+                )
+                .on_input(move |binding| Message::KeyBindingInput(action, binding))
+                .on_submit(move |_| Message::CommitKeyBinding(action))
+                .width(Length::Fixed(160.0)),
+            ));
+        }
+
+        let sections = widget::column()
             .push(conv_section)
-            .push(models_section)
-            .push(spacer)
+            .push(export_section)
+            .push(import_section)
+            .push(settings::section().title(fl!("usage")).add(settings::item(
+                fl!("usage-statistics"),
+                widget::button::standard(fl!("open")).on_press(Message::UsagePage),
+            )))
+            .push(
+                settings::section()
+                    .title(fl!("prompt-templates"))
+                    .add(settings::item(
+                        fl!("prompt-templates-count", count = self.templates.len()),
+                        widget::button::standard(fl!("open-folder"))
+                            .on_press(Message::OpenTemplates),
+                    )),
+            )
+            .push(keys_section)
+            .push(servers_section)
+            .push_maybe(backend.manages_models().then_some(models_section))
+            .spacing(20);
+
+        let mut content = widget::column()
+            .push(widget::scrollable(sections).height(Length::Fill))
             .spacing(20);
 
         if !self.status_area_status.is_empty() {
@@ -620,7 +2709,430 @@ impl Window {
             .into()
     }
 
-    fn bot_bubble(&self, message: String) -> Element<'_, Message> {
+    fn usage_view(&self) -> Element<'_, Message> {
+        let mut models_section = settings::section().title(fl!("usage-per-model"));
+        for model in &self.usage.models {
+            let rate = model
+                .tokens_per_second
+                .map(|rate| format!("{:.1}", rate))
+                .unwrap_or_else(|| "-".into());
+
+            models_section = models_section.add(settings::item(
+                model.model.clone(),
+                widget::text::caption(fl!(
+                    "usage-model-summary",
+                    requests = model.requests,
+                    rate = rate,
+                    cold = model.cold_loads
+                )),
+            ));
+        }
+
+        let mut daily_section = settings::section().title(fl!("usage-per-day"));
+        for day in &self.usage.daily {
+            daily_section = daily_section.add(settings::item(
+                format!("{}  {}", day.day, day.model),
+                widget::text::caption(fl!("usage-tokens", tokens = day.tokens)),
+            ));
+        }
+
+        let mut content = widget::column().spacing(20);
+        if self.usage.models.is_empty() {
+            content = content.push(widget::text(fl!("usage-empty")));
+        } else {
+            content = content.push(models_section).push(daily_section);
+        }
+
+        widget::Container::new(padded_control(
+            widget::scrollable(content).height(Length::Fill),
+        ))
+        .height(Length::Fill)
+        .into()
+    }
+
+    /// Searchable list of the server's models: favorites first, the rest
+    /// grouped by family.
+    fn models_view(&self) -> Element<'_, Message> {
+        let favorites = &self.settings.server().favorites;
+
+        let mut models: Vec<&api::Model> = self
+            .model_tags
+            .iter()
+            .filter(|model| matches(model, &self.model_search))
+            .collect();
+        models.sort_by(|a, b| a.model.cmp(&b.model));
+
+        let mut families: BTreeMap<String, Vec<&api::Model>> = BTreeMap::new();
+        let mut favorites_section = settings::section().title(fl!("favorites"));
+        let mut has_favorites = false;
+
+        for model in &models {
+            if favorites.contains(&model.model) {
+                favorites_section = favorites_section.add(self.model_row(model, true));
+                has_favorites = true;
+            } else {
+                families
+                    .entry(family(model).map_or_else(|| fl!("family-other"), str::to_string))
+                    .or_default()
+                    .push(model);
+            }
+        }
+
+        let mut content = widget::column().spacing(20);
+        if models.is_empty() {
+            content = content.push(widget::text(fl!("no-models-found")));
+        }
+        if has_favorites {
+            content = content.push(favorites_section);
+        }
+        for (family, models) in families {
+            let mut section = settings::section().title(family);
+            for model in models {
+                section = section.add(self.model_row(model, false));
+            }
+            content = content.push(section);
+        }
+
+        let search = widget::search_input(fl!("search-models"), &self.model_search)
+            .on_input(Message::ModelSearch)
+            .on_clear(Message::ModelSearch(String::new()));
+
+        widget::Container::new(padded_control(
+            widget::column()
+                .push(search)
+                .push(widget::scrollable(content).height(Length::Fill))
+                .spacing(12),
+        ))
+        .height(Length::Fill)
+        .into()
+    }
+
+    fn model_row<'a>(&'a self, model: &'a api::Model, favorite: bool) -> Element<'a, Message> {
+        let star = widget::button::icon(widget::icon::from_name(if favorite {
+            "starred-symbolic"
+        } else {
+            "non-starred-symbolic"
+        }))
+        .extra_small()
+        .tooltip(fl!("favorite"))
+        .on_press(Message::ToggleFavorite(model.model.clone()));
+
+        let mut name = widget::row()
+            .push(widget::text(&model.model))
+            .align_y(iced::Alignment::Center)
+            .spacing(6);
+        if model.model == self.selected_model {
+            name = name.push(widget::icon::from_name("object-select-symbolic").size(16));
+        }
+
+        let mut badges = widget::row().spacing(4);
+        for capability in capabilities(model) {
+            badges = badges.push(widget::tooltip(
+                widget::icon::from_name(capability.icon()).size(16),
+                widget::text(capability.name()),
+                widget::tooltip::Position::Top,
+            ));
+        }
+
+        let details = widget::row()
+            .push(
+                widget::column()
+                    .push(name)
+                    .push(widget::text::caption(description(model)))
+                    .width(Length::Fill),
+            )
+            .push(badges)
+            .align_y(iced::Alignment::Center);
+
+        let compare = widget::button::icon(widget::icon::from_name(
+            if self.compare_models.contains(&model.model) {
+                "object-select-symbolic"
+            } else {
+                "view-dual-symbolic"
+            },
+        ))
+        .extra_small()
+        .tooltip(fl!("compare"))
+        .on_press(Message::ToggleCompareModel(model.model.clone()));
+
+        let preset = widget::button::icon(widget::icon::from_name("document-properties-symbolic"))
+            .extra_small()
+            .tooltip(fl!("model-preset"))
+            .on_press(Message::EditPreset(model.model.clone()));
+
+        widget::row()
+            .push(star)
+            .push(
+                widget::button::custom(details)
+                    .class(theme::Button::MenuItem)
+                    .width(Length::Fill)
+                    .on_press(Message::ChangeModel(model.model.clone())),
+            )
+            .push(compare)
+            .push(preset)
+            .align_y(iced::Alignment::Center)
+            .spacing(8)
+            .into()
+    }
+
+    /// Details of a model and the preset applied when it is selected.
+    fn preset_view(&self) -> Element<'_, Message> {
+        let model = self
+            .model_tags
+            .iter()
+            .find(|model| model.model == self.preset_model);
+        let draft = &self.preset_draft;
+
+        let input = |field: PresetField, placeholder: &'static str, value: &str| {
+            widget::text_input(placeholder, value.to_string())
+                .on_input(move |value| Message::PresetInput(field, value))
+        };
+
+        let mut details = settings::section().title(self.preset_model.clone());
+        if let Some(model) = model {
+            details = details.add(widget::text::caption(description(model)));
+
+            let capabilities: Vec<String> = capabilities(model)
+                .iter()
+                .map(|capability| capability.name())
+                .collect();
+            if !capabilities.is_empty() {
+                details = details.add(settings::item(
+                    fl!("capabilities"),
+                    widget::text::caption(capabilities.join(", ")),
+                ));
+            }
+        }
+
+        let preset = settings::section()
+            .title(fl!("model-preset"))
+            .add(settings::item(
+                fl!("system-prompt"),
+                input(PresetField::SystemPrompt, "", &draft.system_prompt),
+            ))
+            .add(settings::item(
+                fl!("temperature"),
+                input(PresetField::Temperature, "0.8", &draft.temperature),
+            ))
+            .add(settings::item(
+                fl!("top-p"),
+                input(PresetField::TopP, "0.9", &draft.top_p),
+            ))
+            .add(settings::item(
+                fl!("top-k"),
+                input(PresetField::TopK, "40", &draft.top_k),
+            ))
+            .add(settings::item(
+                fl!("context-size"),
+                input(PresetField::NumCtx, "4096", &draft.num_ctx),
+            ))
+            .add(settings::item(
+                fl!("seed"),
+                input(PresetField::Seed, "", &draft.seed),
+            ))
+            .add(settings::item(
+                fl!("keep-alive"),
+                input(PresetField::KeepAlive, "", &draft.keep_alive),
+            ))
+            .add(settings::item(
+                fl!("think"),
+                widget::dropdown(&self.think_options, Some(draft.think), Message::PresetThink),
+            ))
+            .add(settings::item_row(vec![
+                widget::button::standard(fl!("back"))
+                    .on_press(Message::ModelsPage)
+                    .into(),
+                widget::Space::with_width(Length::Fill).into(),
+                widget::button::standard(fl!("reset"))
+                    .on_press(Message::ResetPreset)
+                    .into(),
+                widget::button::suggested(fl!("save"))
+                    .on_press(Message::SavePreset)
+                    .into(),
+            ]));
+
+        widget::Container::new(padded_control(
+            widget::scrollable(widget::column().push(details).push(preset).spacing(20))
+                .height(Length::Fill),
+        ))
+        .height(Length::Fill)
+        .into()
+    }
+
+    fn completions_view(&self, completions: Vec<(usize, &Template)>) -> Element<'_, Message> {
+        let mut list = widget::column().spacing(2);
+
+        for (index, template) in completions {
+            list = list.push(
+                widget::button::custom(
+                    widget::row()
+                        .push(widget::text(format!("/{}", template.name)))
+                        .push(widget::text::caption(template.description.clone()))
+                        .align_y(iced::Alignment::Center)
+                        .spacing(10),
+                )
+                .class(theme::Button::MenuItem)
+                .width(Length::Fill)
+                .on_press(Message::UseTemplate(index)),
+            );
+        }
+
+        list.into()
+    }
+
+    fn template_form_view(&self, form: &TemplateForm) -> Element<'_, Message> {
+        let name = self
+            .templates
+            .get(form.index)
+            .map(|template| template.name.clone())
+            .unwrap_or_default();
+
+        let mut column = widget::column()
+            .push(widget::text::heading(format!("/{}", name)))
+            .spacing(8);
+
+        for (index, (variable, value)) in form.values.iter().enumerate() {
+            let mut input = widget::text_input(variable.clone(), value.clone())
+                .on_input(move |value| Message::TemplateInput(index, value))
+                .width(Length::Fill);
+            if index + 1 == form.values.len() {
+                input = input.on_submit(|_| Message::SendTemplate);
+            }
+
+            column = column.push(
+                widget::row()
+                    .push(widget::text(variable.clone()).width(Length::Fixed(120.0)))
+                    .push(input)
+                    .align_y(iced::Alignment::Center)
+                    .spacing(10),
+            );
+        }
+
+        column
+            .push(
+                widget::row()
+                    .push(widget::Space::with_width(Length::Fill))
+                    .push(widget::button::standard(fl!("cancel")).on_press(Message::CancelTemplate))
+                    .push(
+                        widget::button::suggested(fl!("send"))
+                            .on_press_maybe((form.reads == 0).then_some(Message::SendTemplate)),
+                    )
+                    .spacing(10),
+            )
+            .into()
+    }
+
+    /// Long pasted texts, sent as fenced blocks after the prompt.
+    fn pasted_view(&self) -> Element<'_, Message> {
+        let mut chips = widget::row().spacing(8);
+
+        for (index, text) in self.pasted.iter().enumerate() {
+            chips = chips.push(
+                widget::button::standard(fl!("pasted-text", lines = text.lines().count()))
+                    .leading_icon(widget::icon::from_name("text-x-generic-symbolic"))
+                    .tooltip(fl!("pasted-text-expand"))
+                    .on_press(Message::ExpandPasted(index)),
+            );
+            chips = chips.push(
+                widget::button::icon(widget::icon::from_name("window-close-symbolic"))
+                    .extra_small()
+                    .tooltip(fl!("remove"))
+                    .on_press(Message::RemovePasted(index)),
+            );
+        }
+
+        chips.align_y(iced::Alignment::Center).into()
+    }
+
+    fn prompt_counter(&self) -> String {
+        let characters = self.prompt().trim_end().chars().count()
+            + self
+                .pasted
+                .iter()
+                .map(|text| text.chars().count())
+                .sum::<usize>();
+        let tokens = context::estimate(self.prompt().trim_end())
+            + self
+                .pasted
+                .iter()
+                .map(|text| context::estimate(text))
+                .sum::<u64>();
+
+        fl!("prompt-counter", characters = characters, tokens = tokens)
+    }
+
+    /// Pending prompts, oldest first.
+    fn queue_view(&self) -> Element<'_, Message> {
+        let mut queue = widget::column()
+            .push(widget::text::caption(fl!("queued-prompts")))
+            .spacing(4);
+
+        for (index, prompt) in self.queue.iter().enumerate() {
+            let send = (index == 0 && self.is_idle() && self.comparison.is_empty())
+                .then_some(Message::SendQueued);
+
+            queue = queue.push(
+                widget::row()
+                    .push(widget::text(prompt.clone()).width(Length::Fill))
+                    .push(
+                        widget::button::icon(widget::icon::from_name("mail-send-symbolic"))
+                            .extra_small()
+                            .tooltip(fl!("send-now"))
+                            .on_press_maybe(send),
+                    )
+                    .push(
+                        widget::button::icon(widget::icon::from_name("go-up-symbolic"))
+                            .extra_small()
+                            .tooltip(fl!("move-up"))
+                            .on_press_maybe((index > 0).then_some(Message::QueueUp(index))),
+                    )
+                    .push(
+                        widget::button::icon(widget::icon::from_name("edit-delete-symbolic"))
+                            .extra_small()
+                            .tooltip(fl!("remove"))
+                            .on_press(Message::QueueRemove(index)),
+                    )
+                    .align_y(iced::Alignment::Center)
+                    .spacing(4),
+            );
+        }
+
+        queue.into()
+    }
+
+    /// The replies of a comparison side by side, each with its statistics
+    /// and a button to continue the conversation with it.
+    fn comparison_view(&self) -> Element<'_, Message> {
+        let mut row = widget::row().spacing(10);
+
+        for (index, candidate) in self.comparison.iter().enumerate() {
+            let mut column = widget::column()
+                .push(widget::text::heading(candidate.model.clone()))
+                .spacing(8)
+                .width(Length::FillPortion(1));
+
+            if !candidate.text.is_empty() {
+                column = column.push(self.bot_bubble(candidate.text.clone(), None));
+            }
+            if let Some(why) = &candidate.error {
+                column = column.push(self.system_bubble(why.clone()));
+            }
+            if let Some(stats) = &candidate.stats {
+                column = column.push(self.stats_footer(stats));
+            }
+
+            column = column.push(
+                widget::button::suggested(fl!("compare-pick"))
+                    .on_press_maybe(candidate.done.then_some(Message::PickReply(index))),
+            );
+
+            row = row.push(column);
+        }
+
+        row.into()
+    }
+
+    fn bot_bubble(&self, message: String, id: Option<usize>) -> Element<'_, Message> {
         let content: Vec<iced::widget::markdown::Item> =
             widget::markdown::parse(&message).collect();
         let markdown = iced::widget::markdown(
@@ -641,12 +3153,93 @@ impl Window {
             .height(Length::Fixed(48.0));
 
         let message_row = widget::row().push(avatar_widget).push(ai).spacing(12);
-        let content = widget::column().push(message_row);
+        let mut content = widget::column().push(message_row).spacing(4);
+
+        if let Some(id) = id {
+            let stopped = self
+                .conversation
+                .nodes
+                .get(id)
+                .is_some_and(|node| node.stopped);
+            let can_continue = stopped && self.conversation.leaf == Some(id);
+
+            content = content.push(
+                widget::row()
+                    .push(widget::Space::with_width(Length::Fixed(60.0)))
+                    .push_maybe(stopped.then(|| widget::text::caption(fl!("reply-stopped"))))
+                    .push_maybe(can_continue.then(|| {
+                        self.editing_action(
+                            "media-playback-start-symbolic",
+                            fl!("continue-reply"),
+                            Message::ContinueReply(id),
+                        )
+                    }))
+                    .push(self.editing_action(
+                        "view-refresh-symbolic",
+                        fl!("regenerate"),
+                        Message::Regenerate(id),
+                    ))
+                    .push(self.editing_action(
+                        "edit-copy-symbolic",
+                        fl!("fork-from-here"),
+                        Message::Fork(id),
+                    ))
+                    .push_maybe(self.branch_switcher(id))
+                    .push_maybe(self.stats(id).map(|_| {
+                        self.message_action(
+                            "dialog-information-symbolic",
+                            fl!("stats-show"),
+                            Some(Message::ToggleStats(id)),
+                        )
+                    }))
+                    .align_y(iced::Alignment::Center),
+            );
+
+            if let Some(stats) = self.stats(id).filter(|_| self.expanded_stats.contains(&id)) {
+                content = content.push(
+                    widget::row()
+                        .push(widget::Space::with_width(Length::Fixed(60.0)))
+                        .push(self.stats_footer(stats)),
+                );
+            }
+        }
 
         widget::Container::new(content).into()
     }
 
-    fn user_bubble(&self, message: &MessageContent) -> Element<'_, Message> {
+    fn stats(&self, id: usize) -> Option<&Stats> {
+        self.conversation.nodes.get(id)?.stats.as_ref()
+    }
+
+    fn stats_footer(&self, stats: &Stats) -> Element<'_, Message> {
+        let mut lines: Vec<String> = Vec::new();
+
+        if let Some(rate) = stats.tokens_per_second() {
+            lines.push(fl!(
+                "stats-tokens-per-second",
+                rate = format!("{:.1}", rate)
+            ));
+        }
+        if let Some(duration) = stats.time_to_first_token {
+            lines.push(fl!("stats-first-token", duration = seconds(duration)));
+        }
+        if let Some(duration) = stats.load_duration {
+            lines.push(fl!("stats-load", duration = seconds(duration)));
+        }
+        if let Some(count) = stats.prompt_eval_count {
+            lines.push(fl!("stats-prompt-tokens", count = count));
+        }
+        if let Some(count) = stats.eval_count {
+            lines.push(fl!("stats-response-tokens", count = count));
+        }
+        if let Some(duration) = stats.total_duration {
+            lines.push(fl!("stats-total", duration = seconds(duration)));
+        }
+
+        widget::text::caption(lines.join(" · ")).into()
+    }
+
+    fn user_bubble(&self, message: &MessageContent, id: usize) -> Element<'_, Message> {
         let mut column = widget::column();
 
         match message {
@@ -689,7 +3282,31 @@ impl Window {
             .push(avatar_widget)
             .spacing(12);
 
-        widget::Container::new(message_row)
+        let mut content = widget::column()
+            .push(message_row)
+            .align_x(iced::Alignment::End)
+            .spacing(4);
+
+        if let MessageContent::Text(_) = message {
+            content = content.push(
+                widget::row()
+                    .push_maybe(self.branch_switcher(id))
+                    .push(self.editing_action(
+                        "edit-copy-symbolic",
+                        fl!("fork-from-here"),
+                        Message::Fork(id),
+                    ))
+                    .push(self.editing_action(
+                        "edit-symbolic",
+                        fl!("edit-message"),
+                        Message::EditMessage(id),
+                    ))
+                    .push(widget::Space::with_width(Length::Fixed(60.0)))
+                    .align_y(iced::Alignment::Center),
+            );
+        }
+
+        widget::Container::new(content)
             .width(Length::Fill)
             .align_x(Horizontal::Right)
             .into()
@@ -698,13 +3315,18 @@ impl Window {
     fn chat_messages(&self, conv: &Conversation) -> Element<'_, Message> {
         let mut content = widget::column().spacing(20);
 
-        for c in &conv.messages {
-            match c {
-                Text::User(message) => content = content.push(self.user_bubble(message)),
+        for node in conv.branch() {
+            match &node.text {
+                Text::User(message) => content = content.push(self.user_bubble(message, node.id)),
                 Text::Bot(text) => match text {
                     MessageContent::Text(txt) => {
+                        let txt = if self.continuing == Some(node.id) {
+                            format!("{}{}", txt, self.bot_response)
+                        } else {
+                            txt.clone()
+                        };
                         if !txt.is_empty() {
-                            content = content.push(self.bot_bubble(txt.clone()))
+                            content = content.push(self.bot_bubble(txt, Some(node.id)))
                         }
                     }
                     MessageContent::Image(_) => todo!(),
@@ -715,6 +3337,25 @@ impl Window {
         widget::Container::new(content).width(Length::Fill).into()
     }
 
+    fn summary_bubble(&self, summary: &str) -> Element<'_, Message> {
+        let content = widget::column()
+            .push(
+                widget::row()
+                    .push(widget::icon::from_name("view-pin-symbolic").size(16).icon())
+                    .push(widget::text::heading(fl!("summary-of-earlier-messages")))
+                    .align_y(iced::Alignment::Center)
+                    .spacing(8),
+            )
+            .push(widget::text(summary.to_string()))
+            .spacing(8);
+
+        widget::Container::new(content)
+            .padding(12)
+            .width(Length::Fill)
+            .class(theme::Container::List)
+            .into()
+    }
+
     fn system_bubble(&self, message: String) -> Element<'_, Message> {
         let user = widget::Container::new(
             widget::Container::new(widget::text(message))
@@ -741,10 +3382,64 @@ impl Window {
                 widget::button::standard(fl!("settings"))
                     .on_press(Message::SettingsPage)
                     .into(),
-                widget::dropdown(&self.models, self.model_index, Message::ChangeModel)
-                    .width(Length::Fill)
-                    .into(),
+                widget::dropdown(
+                    &self.server_names,
+                    Some(self.settings.active_server),
+                    Message::SelectServer,
+                )
+                .into(),
+                widget::button::standard(if self.selected_model.is_empty() {
+                    fl!("choose-model")
+                } else {
+                    self.selected_model.clone()
+                })
+                .trailing_icon(widget::icon::from_name("pan-down-symbolic"))
+                .on_press(Message::ModelsPage)
+                .into(),
             ]))
             .into()
     }
 }
+
+/// Asks the Screenshot portal for a capture, letting the user pick the
+/// screen or region. `None` when the user cancels.
+async fn capture_screen() -> anyhow::Result<Option<Image>> {
+    let response = Screenshot::request()
+        .interactive(true)
+        .modal(true)
+        .send()
+        .await?
+        .response();
+
+    let screenshot = match response {
+        Ok(screenshot) => screenshot,
+        Err(ashpd::Error::Response(ResponseError::Cancelled)) => return Ok(None),
+        Err(why) => return Err(why.into()),
+    };
+
+    let path = screenshot
+        .uri()
+        .to_file_path()
+        .map_err(|_| anyhow::anyhow!("not a local file: {}", screenshot.uri()))?;
+    let data = std::fs::read(path)?;
+    Ok(Some(Image::from_bytes(data)))
+}
+
+/// The system prompt of the preset, if any, followed by the history.
+fn request_messages(preset: &ModelPreset, history: &[Turn]) -> Vec<ChatMessage> {
+    let mut messages: Vec<ChatMessage> = Vec::new();
+    if !preset.system_prompt.is_empty() {
+        messages.push(ChatMessage {
+            role: Role::System,
+            content: preset.system_prompt.clone(),
+            images: Vec::new(),
+        });
+    }
+    messages.extend(history.iter().map(|turn| turn.message.clone()));
+
+    messages
+}
+
+fn seconds(nanoseconds: u64) -> String {
+    format!("{:.2}", nanoseconds as f64 / 1e9)
+}